@@ -0,0 +1,397 @@
+use clap::{Parser, ValueEnum};
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use webpixels::{
+    Core, FrameSeeds, Options, OutputFormat, Pipeline, Recipe,
+    decode::{self, Format},
+    encode::JPEG_QUALITY,
+    seed_from_phrase,
+};
+
+/// Glitch images with the webpixels pipeline
+#[derive(Parser)]
+#[command(name = "webpixels", version, author)]
+struct Cli {
+    /// Image files or glob patterns, `-` reads standard input
+    #[arg(required = true, value_name = "INPUT")]
+    inputs: Vec<String>,
+
+    /// Output file, or directory for several inputs, `-` writes
+    /// standard output
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Options JSON, recipe JSON or PNG with an embedded recipe,
+    /// flags take precedence
+    #[arg(long, value_name = "FILE")]
+    recipe: Option<PathBuf>,
+
+    /// Pipeline JSON, every pass moshes the output of the previous one
+    /// with its own options
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["recipe", "derive_seeds"])]
+    pipeline: Option<PathBuf>,
+
+    /// Minimum chunks to process
+    #[arg(short = 'n', long, value_name = "VALUE")]
+    min_rate: Option<u16>,
+
+    /// Maximum chunks to process
+    #[arg(short = 'm', long, value_name = "VALUE")]
+    max_rate: Option<u16>,
+
+    /// Pixelation rate
+    #[arg(short, long, value_name = "VALUE")]
+    pixelation: Option<u8>,
+
+    /// Line shift rate
+    #[arg(short, long, value_name = "VALUE")]
+    line_shift: Option<f64>,
+
+    /// Reverse rate
+    #[arg(short, long, value_name = "VALUE")]
+    reverse: Option<f64>,
+
+    /// Flip rate
+    #[arg(short, long, value_name = "VALUE")]
+    flip: Option<f64>,
+
+    /// Channel swap rate
+    #[arg(short = 'c', long, value_name = "VALUE")]
+    channel_swap: Option<f64>,
+
+    /// Channel shift rate
+    #[arg(short = 't', long, value_name = "VALUE")]
+    channel_shift: Option<f64>,
+
+    /// Use ANSI color palette
+    #[arg(short, long)]
+    ansi: bool,
+
+    /// Custom seed, random by default
+    #[arg(short, long, value_name = "VALUE")]
+    seed: Option<u64>,
+
+    /// Seed from the hash of a phrase
+    #[arg(long, value_name = "TEXT", conflicts_with = "seed")]
+    seed_phrase: Option<String>,
+
+    /// Give every input its own seed derived from the seed
+    #[arg(long)]
+    derive_seeds: bool,
+
+    /// Seeds used for frames of animated input
+    #[arg(long, value_enum, default_value_t = FrameSeedMode::Fixed)]
+    frame_seeds: FrameSeedMode,
+
+    /// Output format of still images, PNG unless the recipe sets one
+    #[arg(long, value_enum)]
+    format: Option<OutputKind>,
+
+    /// Quality of JPEG or lossy WebP output, WebP is lossless without it
+    #[arg(long, value_name = "VALUE")]
+    quality: Option<u8>,
+
+    /// Store settings in PNG output
+    #[arg(long)]
+    embed_recipe: bool,
+}
+
+/// Path of standard input and output
+const STDIO: &str = "-";
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FrameSeedMode {
+    Fixed,
+    PerFrame,
+    Derived,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputKind {
+    Png,
+    Jpeg,
+    Webp,
+    Qoi,
+}
+
+impl Cli {
+    fn recipe(&self) -> Result<Recipe, String> {
+        let mut recipe = match &self.recipe {
+            Some(path) => {
+                read_recipe(path).map_err(|error| format!("{}: {error}", path.display()))?
+            }
+            None => Recipe::new(Options::default()),
+        };
+
+        let options = &mut recipe.options;
+        options.ansi |= self.ansi;
+        options.min_rate = self.min_rate.unwrap_or(options.min_rate);
+        options.max_rate = self.max_rate.unwrap_or(options.max_rate);
+        options.pixelation = self.pixelation.unwrap_or(options.pixelation);
+        options.line_shift = self.line_shift.unwrap_or(options.line_shift);
+        options.reverse = self.reverse.unwrap_or(options.reverse);
+        options.flip = self.flip.unwrap_or(options.flip);
+        options.channel_swap = self.channel_swap.unwrap_or(options.channel_swap);
+        options.channel_shift = self.channel_shift.unwrap_or(options.channel_shift);
+        options.seed = match &self.seed_phrase {
+            Some(phrase) => seed_from_phrase(phrase),
+            None => self.seed.unwrap_or(options.seed),
+        };
+
+        if let Some(format) = self.format {
+            recipe.output = Some(match format {
+                OutputKind::Png => OutputFormat::default(),
+                OutputKind::Jpeg => OutputFormat::Jpeg {
+                    quality: self.quality.unwrap_or(JPEG_QUALITY),
+                },
+                OutputKind::Webp => OutputFormat::Webp {
+                    quality: self.quality,
+                },
+                OutputKind::Qoi => OutputFormat::Qoi,
+            });
+        } else if let Some(quality) = self.quality {
+            recipe.output = recipe.output.map(|output| match output {
+                OutputFormat::Jpeg { .. } => OutputFormat::Jpeg { quality },
+                OutputFormat::Webp { .. } => OutputFormat::Webp {
+                    quality: Some(quality),
+                },
+                output => output,
+            });
+        }
+
+        Ok(recipe)
+    }
+
+    fn core(&self) -> Result<Core, String> {
+        let mut core = Core::default();
+
+        core.set_recipe(&self.recipe()?)
+            .map_err(|error| error.to_string())?;
+        core.set_embed_recipe(self.embed_recipe);
+        core.set_frame_seeds(match self.frame_seeds {
+            FrameSeedMode::Fixed => FrameSeeds::Fixed,
+            FrameSeedMode::PerFrame => FrameSeeds::PerFrame,
+            FrameSeedMode::Derived => FrameSeeds::Derived,
+        });
+
+        Ok(core)
+    }
+
+    fn pipeline(&self) -> Result<Option<Pipeline>, String> {
+        let Some(path) = &self.pipeline else {
+            return Ok(None);
+        };
+
+        fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|json| Pipeline::from_json(&json).map_err(|error| error.to_string()))
+            .map(Some)
+            .map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    /// Expands glob patterns, plain paths are kept as given
+    fn input_paths(&self) -> Result<Vec<PathBuf>, String> {
+        let mut paths = Vec::new();
+
+        for input in &self.inputs {
+            let matches = glob::glob(input)
+                .map_err(|error| format!("{input}: {error}"))?
+                .filter_map(Result::ok)
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+
+            if matches.is_empty() {
+                paths.push(PathBuf::from(input));
+            } else {
+                paths.extend(matches);
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Standard input and output only carry a single image
+    fn check_stdio(&self, inputs: &[PathBuf]) -> Result<(), String> {
+        if inputs.len() < 2 {
+            return Ok(());
+        }
+
+        if self.output.as_deref() == Some(Path::new(STDIO)) {
+            return Err(String::from("standard output takes a single input"));
+        }
+
+        if inputs.iter().any(|input| input == Path::new(STDIO)) {
+            return Err(String::from("standard input takes a single input"));
+        }
+
+        Ok(())
+    }
+
+    /// Inputs with the same stem get numbered names instead of
+    /// overwriting `written` outputs of this run
+    fn output_path(
+        &self,
+        input: &Path,
+        extension: &str,
+        batch: bool,
+        written: &HashSet<PathBuf>,
+    ) -> PathBuf {
+        let directory = match &self.output {
+            Some(output) if batch || output.is_dir() => output.as_path(),
+            Some(output) => return output.clone(),
+            None if input == Path::new(STDIO) => return PathBuf::from(STDIO),
+            None => input.parent().unwrap_or(Path::new("")),
+        };
+
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let mut path = directory.join(format!("{stem}-moshed.{extension}"));
+        let mut count = 1;
+
+        while written.contains(&path) {
+            count += 1;
+            path = directory.join(format!("{stem}-moshed-{count}.{extension}"));
+        }
+
+        path
+    }
+}
+
+fn read_recipe(path: &Path) -> Result<Recipe, String> {
+    let data = fs::read(path).map_err(|error| error.to_string())?;
+
+    if Format::detect(&data) == Some(Format::Png) {
+        return Recipe::from_png(&data).map_err(|error| error.to_string());
+    }
+
+    let json = String::from_utf8_lossy(&data);
+
+    Options::from_json(&json)
+        .map(Recipe::new)
+        .or_else(|error| Recipe::from_json(&json).map_err(|_| error))
+        .map_err(|error| error.to_string())
+}
+
+fn read_input(input: &Path) -> io::Result<Vec<u8>> {
+    if input == Path::new(STDIO) {
+        let mut image = Vec::new();
+        io::stdin().read_to_end(&mut image)?;
+
+        Ok(image)
+    } else {
+        fs::read(input)
+    }
+}
+
+fn write_output(path: &Path, output: &[u8]) -> io::Result<()> {
+    if path == Path::new(STDIO) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(output)?;
+        stdout.flush()
+    } else {
+        fs::write(path, output)
+    }
+}
+
+fn mosh_file(
+    cli: &Cli,
+    core: &mut Core,
+    pipeline: Option<&Pipeline>,
+    input: &Path,
+    batch: bool,
+    written: &mut HashSet<PathBuf>,
+) -> Result<PathBuf, String> {
+    let image = read_input(input).map_err(|error| error.to_string())?;
+
+    // Animated input keeps its own format, pipelines only mosh the first frame
+    let extension = if decode::is_animated(&image) && pipeline.is_none() {
+        Format::detect(&image).map_or("png", |format| match format {
+            Format::Gif => "gif",
+            _ => "png",
+        })
+    } else {
+        core.output().extension()
+    };
+
+    let output = match pipeline {
+        Some(pipeline) => core.run_pipeline(&image, pipeline),
+        None => core.pixelmosh(&image),
+    }
+    .map_err(|error| error.to_string())?;
+    let path = cli.output_path(input, extension, batch, written);
+
+    write_output(&path, &output).map_err(|error| format!("{}: {error}", path.display()))?;
+    written.insert(path.clone());
+
+    Ok(path)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let setup = cli.core().and_then(|core| {
+        let inputs = cli.input_paths()?;
+        cli.check_stdio(&inputs)?;
+
+        Ok((core, cli.pipeline()?, inputs))
+    });
+
+    let (mut core, pipeline, inputs) = match setup {
+        Ok(setup) => setup,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let batch = inputs.len() > 1;
+
+    if batch
+        && let Some(output) = &cli.output
+        && let Err(error) = fs::create_dir_all(output)
+    {
+        eprintln!("error: {}: {error}", output.display());
+        return ExitCode::FAILURE;
+    }
+
+    match &pipeline {
+        Some(pipeline) => eprintln!("pipeline: {} passes", pipeline.len()),
+        None => eprintln!("seed: {}", core.seed()),
+    }
+
+    let mut status = ExitCode::SUCCESS;
+    let mut seeds = core.seed_sequence();
+    let mut written = HashSet::new();
+
+    for input in &inputs {
+        if cli.derive_seeds {
+            core.set_seed(seeds.next_seed());
+            eprintln!("{}: seed {}", input.display(), core.seed());
+        }
+
+        match mosh_file(
+            &cli,
+            &mut core,
+            pipeline.as_ref(),
+            input,
+            batch,
+            &mut written,
+        ) {
+            // Standard output only carries the image
+            Ok(output) if output == Path::new(STDIO) => {}
+            Ok(output) => println!("{} -> {}", input.display(), output.display()),
+            Err(error) => {
+                eprintln!("error: {}: {error}", input.display());
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+
+    status
+}
@@ -0,0 +1,269 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use webpixels::{Blend, BlendMode, Options, OutputFormat, Recipe, SeedSequence};
+
+const IMAGE: &[u8] = include_bytes!("valid.png");
+
+/// Scratch directory removed after the test
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("webpixels-cli-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        Self(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Writes the test image to a relative path
+    fn image(&self, name: &str) -> PathBuf {
+        let path = self.0.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, IMAGE).unwrap();
+
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn webpixels<I, S>(args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    Command::new(env!("CARGO_BIN_EXE_webpixels"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn recipe(path: &Path) -> Options {
+    Recipe::from_png(&fs::read(path).unwrap()).unwrap().options
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn numbered_batch_outputs() {
+    let dir = TempDir::new("batch");
+    let first = dir.image("a/image.png");
+    let second = dir.image("b/image.png");
+    let out = dir.path().join("out");
+
+    let output = webpixels([
+        first.as_os_str(),
+        second.as_os_str(),
+        "-o".as_ref(),
+        out.as_os_str(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    assert!(out.join("image-moshed.png").is_file());
+    assert!(out.join("image-moshed-2.png").is_file());
+}
+
+#[test]
+fn single_output_paths() {
+    let dir = TempDir::new("single");
+    let input = dir.image("image.png");
+
+    let output = webpixels([&input]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(dir.path().join("image-moshed.png").is_file());
+
+    let file = dir.path().join("named.png");
+    let output = webpixels([input.as_os_str(), "-o".as_ref(), file.as_os_str()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(file.is_file());
+
+    let out = dir.path().join("out");
+    fs::create_dir(&out).unwrap();
+    let output = webpixels([input.as_os_str(), "-o".as_ref(), out.as_os_str()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(out.join("image-moshed.png").is_file());
+}
+
+#[test]
+fn flags_override_recipe() {
+    let dir = TempDir::new("precedence");
+    let input = dir.image("image.png");
+    let recipe_path = dir.path().join("options.json");
+    let out = dir.path().join("out.png");
+
+    let options = Options {
+        pixelation: 5,
+        flip: 0.5,
+        seed: 7,
+        ..Options::default()
+    };
+    fs::write(&recipe_path, options.to_json()).unwrap();
+
+    let output = webpixels([
+        input.as_os_str(),
+        "--recipe".as_ref(),
+        recipe_path.as_os_str(),
+        "--pixelation".as_ref(),
+        "9".as_ref(),
+        "--embed-recipe".as_ref(),
+        "-o".as_ref(),
+        out.as_os_str(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let embedded = recipe(&out);
+    assert_eq!(embedded.pixelation, 9);
+    assert_eq!(embedded.flip, 0.5);
+    assert_eq!(embedded.seed, 7);
+}
+
+#[test]
+fn recipe_sources() {
+    let dir = TempDir::new("recipes");
+    let input = dir.image("image.png");
+    let options = Options {
+        seed: 11,
+        ..Options::default()
+    };
+
+    let options_json = dir.path().join("options.json");
+    fs::write(&options_json, options.to_json()).unwrap();
+    let recipe_json = dir.path().join("recipe.json");
+    fs::write(&recipe_json, Recipe::new(options.clone()).to_json()).unwrap();
+    let recipe_png = dir.path().join("recipe.png");
+
+    // The PNG written from the options JSON is read back as the last source
+    for (index, source) in [&options_json, &recipe_json, &recipe_png]
+        .iter()
+        .enumerate()
+    {
+        let out = dir.path().join(format!("out-{index}.png"));
+        let output = webpixels([
+            input.as_os_str(),
+            "--recipe".as_ref(),
+            source.as_os_str(),
+            "--embed-recipe".as_ref(),
+            "-o".as_ref(),
+            out.as_os_str(),
+        ]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(recipe(&out), options);
+
+        if index == 0 {
+            fs::copy(&out, &recipe_png).unwrap();
+        }
+    }
+
+    let invalid = dir.path().join("invalid.json");
+    fs::write(&invalid, "{}").unwrap();
+    let output = webpixels([input.as_os_str(), "--recipe".as_ref(), invalid.as_os_str()]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("invalid.json"));
+}
+
+#[test]
+fn recipe_output_and_blend() {
+    let dir = TempDir::new("recipe-output");
+    let input = dir.image("image.png");
+    let recipe_json = dir.path().join("recipe.json");
+    let out = dir.path().join("out");
+    fs::create_dir(&out).unwrap();
+
+    let recipe = Recipe {
+        blend: Blend {
+            mode: BlendMode::Difference,
+            opacity: 0.5,
+        },
+        output: Some(OutputFormat::Qoi),
+        ..Recipe::new(Options::default())
+    };
+    fs::write(&recipe_json, recipe.to_json()).unwrap();
+
+    let run = |flags: &[&str]| {
+        let mut args = vec![
+            input.as_os_str(),
+            "--recipe".as_ref(),
+            recipe_json.as_os_str(),
+            "-o".as_ref(),
+            out.as_os_str(),
+        ];
+        args.extend(flags.iter().map(std::ffi::OsStr::new));
+
+        webpixels(args)
+    };
+
+    let output = run(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(out.join("image-moshed.qoi").is_file());
+
+    // Flags take precedence over the recipe format
+    let output = run(&["--format", "png", "--embed-recipe"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let embedded = Recipe::from_png(&fs::read(out.join("image-moshed.png")).unwrap()).unwrap();
+    assert_eq!(embedded.blend, recipe.blend);
+    assert_eq!(embedded.output, Some(OutputFormat::default()));
+}
+
+#[test]
+fn derive_seeds() {
+    let dir = TempDir::new("derive");
+    let first = dir.image("first.png");
+    let second = dir.image("second.png");
+    let out = dir.path().join("out");
+
+    let output = webpixels([
+        first.as_os_str(),
+        second.as_os_str(),
+        "--seed".as_ref(),
+        "42".as_ref(),
+        "--derive-seeds".as_ref(),
+        "--embed-recipe".as_ref(),
+        "-o".as_ref(),
+        out.as_os_str(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let mut seeds = SeedSequence::new(42);
+    let first_seed = recipe(&out.join("first-moshed.png")).seed;
+    let second_seed = recipe(&out.join("second-moshed.png")).seed;
+    assert_eq!(first_seed, seeds.next_seed());
+    assert_eq!(second_seed, seeds.next_seed());
+    assert_ne!(first_seed, second_seed);
+}
+
+#[test]
+fn stdio_takes_single_input() {
+    let dir = TempDir::new("stdio");
+    let first = dir.image("first.png");
+    let second = dir.image("second.png");
+
+    let output = webpixels([
+        first.as_os_str(),
+        second.as_os_str(),
+        "-o".as_ref(),
+        "-".as_ref(),
+    ]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("standard output takes a single input"));
+
+    let output = webpixels([first.as_os_str(), "-".as_ref()]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("standard input takes a single input"));
+    assert!(!dir.path().join("first-moshed.png").exists());
+}
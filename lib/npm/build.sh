@@ -0,0 +1,25 @@
+#!/bin/sh
+# Builds the npm package in `lib/pkg` with web, bundler and Node.js targets,
+# extra arguments are passed to wasm-pack (e.g. `-- --features jpeg`)
+set -e
+
+cd "$(dirname "$0")/.."
+rm -rf pkg
+
+for target in web bundler node; do
+  case $target in
+    node) wasm_target=nodejs ;;
+    *) wasm_target=$target ;;
+  esac
+
+  wasm-pack build --release --target "$wasm_target" --out-dir "pkg/$target" \
+    --out-name webpixels "$@"
+
+  # Target directories are parts of a single package
+  rm -f "pkg/$target/.gitignore" "pkg/$target/README.md" "pkg/$target"/LICENSE*
+done
+
+version=$(sed -n 's/^version = "\(.*\)"$/\1/p' Cargo.toml | head -n 1)
+
+sed "s/\"version\": \"0.0.0\"/\"version\": \"$version\"/" npm/package.json > pkg/package.json
+cp README.md pkg/
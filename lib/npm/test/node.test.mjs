@@ -0,0 +1,111 @@
+import assert from "node:assert/strict";
+import { readFileSync } from "node:fs";
+import { createRequire } from "node:module";
+import { test } from "node:test";
+
+const require = createRequire(import.meta.url);
+const { Core, CoreError, Pipeline } = require("../../pkg/node/webpixels.js");
+
+const image = readFileSync(new URL("valid.png", import.meta.url));
+const PNG_SIGNATURE = [0x89, 0x50, 0x4e, 0x47];
+
+test("moshes a PNG file", () => {
+  const output = new Core().pixelmosh(image);
+
+  assert.ok(output instanceof Uint8Array);
+  assert.deepEqual([...output.subarray(0, 4)], PNG_SIGNATURE);
+});
+
+test("options round trip", () => {
+  const core = new Core();
+  core.set_pixelation(4);
+  core.set_flip(0.5);
+
+  const options = core.options();
+  assert.equal(options.version, 1);
+  assert.equal(options.pixelation, 4);
+  assert.equal(options.flip, 0.5);
+  assert.equal(typeof options.seed, "string");
+
+  const copy = Core.from_options(options);
+  assert.deepEqual(copy.options(), options);
+  assert.deepEqual(copy.pixelmosh(image), core.pixelmosh(image));
+});
+
+test("same seed gives identical output", () => {
+  const first = new Core();
+  const second = new Core();
+  first.set_seed(42n);
+  second.set_seed_phrase("glitch");
+  second.set_seed(42n);
+
+  assert.deepEqual(first.pixelmosh(image), second.pixelmosh(image));
+});
+
+test("seed keeps u64 precision", () => {
+  const core = new Core();
+  core.set_options({ ...core.options(), seed: "18446744073709551615" });
+
+  assert.equal(core.seed(), 18446744073709551615n);
+  assert.equal(core.options().seed, "18446744073709551615");
+});
+
+test("remosh reuses the loaded file", () => {
+  const core = new Core();
+  core.load(image);
+
+  assert.deepEqual(core.remosh(), core.remosh());
+});
+
+test("recipe is embedded in the output", () => {
+  const core = new Core();
+  core.set_embed_recipe(true);
+
+  const output = core.pixelmosh(image);
+  assert.deepEqual(Core.recipe_from_png(output).options(), core.options());
+});
+
+test("pipeline survives JSON", () => {
+  const core = new Core();
+  const pipeline = new Pipeline();
+  pipeline.add_pass(core);
+  core.set_seed(7n);
+  core.set_opacity(0.5);
+  pipeline.add_pass(core);
+
+  const copy = Pipeline.from_json(pipeline.to_json());
+  assert.equal(copy.len(), 2);
+  assert.deepEqual(
+    core.run_pipeline(image, copy),
+    core.run_pipeline(image, pipeline),
+  );
+});
+
+test("truncated file throws CoreError", () => {
+  assert.throws(
+    () => new Core().pixelmosh(image.subarray(0, 100)),
+    (error) =>
+      error instanceof CoreError &&
+      error.kind === "corrupt-input" &&
+      error.stage === "decode",
+  );
+});
+
+test("out of range values are rejected", () => {
+  const core = new Core();
+  const options = core.options();
+
+  assert.equal(Core.limits().flip.max, 1);
+  assert.throws(
+    () => core.set_pixelation(0),
+    (error) => error instanceof CoreError && error.kind === "invalid-options",
+  );
+  assert.deepEqual(core.options(), options);
+});
+
+test("invalid options throw CoreError", () => {
+  assert.throws(
+    () => Core.from_options({ version: 2 }),
+    (error) => error instanceof CoreError && error.kind === "invalid-options",
+  );
+});
@@ -0,0 +1,552 @@
+use png::{ColorType, FrameControl};
+#[cfg(feature = "js")]
+use wasm_bindgen::prelude::*;
+
+use libmosh::err::MoshError;
+
+use crate::{
+    Core,
+    composite::{Placement, Rect},
+    decode::{self, Format},
+    error::{Error, Stage},
+    metadata::Metadata,
+    options::Options,
+    progress::Progress,
+    seed::SeedSequence,
+};
+
+/// Animated output encoders
+///
+/// GIF output requires the `gif` feature.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AnimationFormat {
+    #[default]
+    Apng,
+    Gif,
+}
+
+/// Seeds used to process frames of animated input
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum FrameSeeds {
+    /// Every frame uses the current seed.
+    #[default]
+    Fixed,
+    /// Every frame uses the next seed.
+    PerFrame,
+    /// Frames use child seeds of a [`SeedSequence`] rooted at the current seed.
+    Derived,
+    /// Frames take seeds from the list, repeating it when it runs out.
+    Schedule(Vec<u64>),
+}
+
+impl FrameSeeds {
+    /// Seed used to process a frame
+    #[must_use]
+    pub fn seed(&self, start: u64, frame: u32) -> u64 {
+        match self {
+            Self::Fixed => start,
+            Self::PerFrame => start.wrapping_add(u64::from(frame)),
+            Self::Derived => SeedSequence::new(start).seed_at(frame),
+            Self::Schedule(seeds) if seeds.is_empty() => start,
+            Self::Schedule(seeds) => seeds[frame as usize % seeds.len()],
+        }
+    }
+}
+
+/// Frame sequence settings for [`Core::animate`]
+///
+/// Frames start from the current [`Core`] options. Each frame uses a child
+/// seed of a [`SeedSequence`] rooted at the current seed when `vary_seed` is
+/// set, and numeric options are interpolated towards the target options
+/// when one is given.
+#[cfg_attr(feature = "js", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct Animation {
+    /// Number of frames.
+    pub frames: u32,
+    /// Frame delay in milliseconds.
+    pub delay: u16,
+    /// Number of plays, `0` loops forever.
+    pub loops: u32,
+    /// Derive a new seed for every frame.
+    pub vary_seed: bool,
+    format: AnimationFormat,
+    target: Option<Options>,
+}
+
+#[cfg_attr(feature = "js", wasm_bindgen)]
+impl Animation {
+    #[cfg_attr(feature = "js", wasm_bindgen(constructor))]
+    pub fn new(frames: u32) -> Animation {
+        Self {
+            frames,
+            delay: 100,
+            loops: 0,
+            vary_seed: true,
+            format: AnimationFormat::default(),
+            target: None,
+        }
+    }
+
+    /// Returns the output format name: `apng` or `gif`
+    pub fn format(&self) -> String {
+        match self.format {
+            AnimationFormat::Apng => "apng",
+            AnimationFormat::Gif => "gif",
+        }
+        .to_string()
+    }
+
+    /// Selects the output format: `apng` or `gif`
+    ///
+    /// # Errors
+    ///
+    /// It fails if the format is unknown or not enabled.
+    pub fn set_format(&mut self, format: &str) -> Result<(), Error> {
+        self.format = match format {
+            "apng" => AnimationFormat::Apng,
+            "gif" if cfg!(feature = "gif") => AnimationFormat::Gif,
+            "gif" => {
+                return Err(Error::InvalidOptions(String::from(
+                    "GIF support is not enabled",
+                )));
+            }
+            _ => {
+                return Err(Error::InvalidOptions(format!(
+                    "Unknown animation format: {format}"
+                )));
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Interpolates options towards JSON settings on the last frame
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the JSON is malformed, uses an unknown schema version
+    /// or options are out of range.
+    pub fn set_target_json(&mut self, json: &str) -> Result<(), Error> {
+        self.target = Some(Options::from_json(json)?);
+
+        Ok(())
+    }
+
+    pub fn clear_target(&mut self) {
+        self.target = None;
+    }
+}
+
+impl Animation {
+    #[must_use]
+    pub fn animation_format(&self) -> AnimationFormat {
+        self.format
+    }
+
+    pub fn set_animation_format(&mut self, format: AnimationFormat) {
+        self.format = format;
+    }
+
+    #[must_use]
+    pub fn target(&self) -> Option<&Options> {
+        self.target.as_ref()
+    }
+
+    /// Interpolates options towards the target on the last frame
+    pub fn set_target(&mut self, target: Option<Options>) {
+        self.target = target;
+    }
+
+    /// Options used to render a frame
+    #[must_use]
+    pub fn frame_options(&self, start: &Options, frame: u32) -> Options {
+        let mut options = match &self.target {
+            Some(target) if self.frames > 1 => {
+                let t = f64::from(frame) / f64::from(self.frames - 1);
+                start.interpolate(target, t)
+            }
+            _ => start.clone(),
+        };
+
+        if self.vary_seed {
+            options.seed = FrameSeeds::Derived.seed(start.seed, frame);
+        }
+
+        options
+    }
+}
+
+#[cfg_attr(feature = "js", wasm_bindgen)]
+impl Core {
+    /// Returns the seed mode for animated input: `fixed`, `per-frame`,
+    /// `derived` or `schedule`
+    #[cfg_attr(feature = "js", wasm_bindgen(unchecked_return_type = "FrameSeedMode"))]
+    pub fn frame_seed_mode(&self) -> String {
+        match self.frame_seeds {
+            FrameSeeds::Fixed => "fixed",
+            FrameSeeds::PerFrame => "per-frame",
+            FrameSeeds::Derived => "derived",
+            FrameSeeds::Schedule(_) => "schedule",
+        }
+        .to_string()
+    }
+
+    /// Selects the seed mode for animated input: `fixed`, `per-frame` or `derived`
+    ///
+    /// # Errors
+    ///
+    /// It fails if the mode is unknown.
+    pub fn set_frame_seed_mode(&mut self, mode: &str) -> Result<(), Error> {
+        self.frame_seeds = match mode {
+            "fixed" => FrameSeeds::Fixed,
+            "per-frame" => FrameSeeds::PerFrame,
+            "derived" => FrameSeeds::Derived,
+            _ => {
+                return Err(Error::InvalidOptions(format!(
+                    "Unknown frame seed mode: {mode}"
+                )));
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Processes frames of animated input with seeds from the list
+    pub fn set_seed_schedule(&mut self, seeds: Vec<u64>) {
+        self.frame_seeds = FrameSeeds::Schedule(seeds);
+    }
+
+    /// Renders an animation from provided image data
+    ///
+    /// # Errors
+    ///
+    /// It fails under the same conditions as [`Core::pixelmosh`], or if
+    /// the animation has no frames.
+    pub fn animate(&mut self, image: &[u8], animation: &Animation) -> Result<Vec<u8>, Error> {
+        self.animate_with_progress(image, animation, &mut Progress::default())
+    }
+}
+
+impl Core {
+    /// Renders an animation and reports progress
+    ///
+    /// # Errors
+    ///
+    /// It fails under the same conditions as [`Core::animate`], or with
+    /// [`Cancelled`](Error::Cancelled) once the token is cancelled.
+    pub fn animate_with_progress(
+        &mut self,
+        image: &[u8],
+        animation: &Animation,
+        progress: &mut Progress,
+    ) -> Result<Vec<u8>, Error> {
+        if animation.frames == 0 {
+            return Err(Error::InvalidOptions(String::from(
+                "Animation needs at least one frame",
+            )));
+        }
+
+        progress.report(Stage::Decode, 0)?;
+        self.read(image)?;
+
+        let start = self.options();
+        let frames = (0..animation.frames).map(|frame| {
+            self.set_options(&animation.frame_options(&start, frame))?;
+            self.mosh_buffer(progress, Progress::span(frame, animation.frames))?;
+
+            Ok(self.mosh.data.buf.clone())
+        });
+
+        let frames: Result<Vec<Vec<u8>>, Error> = frames.collect();
+
+        start.apply(&mut self.mosh.options);
+
+        let frames = frames?;
+        progress.report(Stage::Encode, 90)?;
+
+        let output = match animation.format {
+            AnimationFormat::Apng => self.encode_apng(&frames, animation),
+            AnimationFormat::Gif => self.encode_gif(frames, animation),
+        }?;
+        progress.report(Stage::Encode, 100)?;
+
+        Ok(output)
+    }
+
+    #[must_use]
+    pub fn frame_seeds(&self) -> &FrameSeeds {
+        &self.frame_seeds
+    }
+
+    /// Selects seeds used to process frames of animated input
+    pub fn set_frame_seeds(&mut self, frame_seeds: FrameSeeds) {
+        self.frame_seeds = frame_seeds;
+    }
+
+    /// Processes every frame of animated input
+    ///
+    /// Animated input is encoded in its own format.
+    pub(crate) fn mosh_animated(
+        &mut self,
+        image: &[u8],
+        progress: &mut Progress,
+    ) -> Result<Vec<u8>, Error> {
+        let start = self.mosh.options.seed;
+        let output = match Format::detect(image) {
+            Some(Format::Png) => self.mosh_apng(image, progress),
+            #[cfg(feature = "gif")]
+            Some(Format::Gif) => self.mosh_gif(image, progress),
+            _ => Err(not_animated()),
+        };
+
+        self.mosh.options.seed = start;
+        self.placement = None;
+
+        output
+    }
+
+    fn mosh_apng(&mut self, image: &[u8], progress: &mut Progress) -> Result<Vec<u8>, Error> {
+        let decoding = |e: png::DecodingError| Error::decoding(MoshError::from(e));
+        let mut reader = decode::png_reader(image)?;
+
+        let info = reader.info();
+        let Some(animation) = info.animation_control else {
+            return Err(not_animated());
+        };
+
+        let (width, height) = info.size();
+        let palette = info.palette.as_ref().map(|palette| palette.to_vec());
+        // The default image is not part of the animation without its own fcTL
+        let separate_default = info.frame_control.is_none();
+        let count = animation.num_frames + u32::from(separate_default);
+
+        self.metadata = Metadata::read(image)?;
+
+        let size = reader
+            .output_buffer_size()
+            .ok_or(Error::ImageTooLarge(Stage::Decode))?;
+        let mut buf = vec![0_u8; size];
+        let start = self.mosh.options.seed;
+        let mut frames: Vec<(Vec<u8>, Option<FrameControl>)> = Vec::new();
+
+        for frame in 0..count {
+            let output = reader.next_frame(&mut buf).map_err(decoding)?;
+            let data = &mut self.mosh.data;
+
+            data.image = buf[..output.buffer_size()].to_vec();
+            data.width = output.width;
+            data.height = output.height;
+            data.color_type = output.color_type;
+            data.bit_depth = output.bit_depth;
+            data.line_size = output.line_size;
+            data.palette = match output.color_type {
+                ColorType::Indexed => palette.clone(),
+                _ => None,
+            };
+
+            let control = if frame == 0 && separate_default {
+                None
+            } else {
+                reader.info().frame_control
+            };
+
+            self.placement = Some(Placement {
+                canvas_width: width,
+                canvas_height: height,
+                frame: Rect::new(
+                    control.map_or(0, |control| control.x_offset),
+                    control.map_or(0, |control| control.y_offset),
+                    output.width,
+                    output.height,
+                ),
+            });
+            self.mosh.options.seed = self.frame_seeds.seed(start, frame);
+            self.mosh_buffer(progress, Progress::span(frame, count))?;
+
+            frames.push((self.mosh.data.buf.clone(), control));
+        }
+
+        self.mosh.options.seed = start;
+        progress.report(Stage::Encode, 90)?;
+
+        let mut output = Vec::new();
+        let mut encoder = self.png_encoder(&mut output, width, height)?;
+
+        encoder.set_animated(animation.num_frames, animation.num_plays)?;
+        encoder.set_sep_def_img(separate_default)?;
+
+        let mut writer = encoder.write_header()?;
+
+        for (buf, control) in frames {
+            if let Some(control) = control {
+                // Frames must stay within the canvas at every step
+                writer.reset_frame_position()?;
+                writer.set_frame_dimension(control.width, control.height)?;
+                writer.set_frame_position(control.x_offset, control.y_offset)?;
+                writer.set_frame_delay(control.delay_num, control.delay_den)?;
+                writer.set_dispose_op(control.dispose_op)?;
+                writer.set_blend_op(control.blend_op)?;
+            }
+
+            writer.write_image_data(&buf)?;
+        }
+
+        writer.finish()?;
+
+        Ok(output)
+    }
+
+    #[cfg(feature = "gif")]
+    fn mosh_gif(&mut self, image: &[u8], progress: &mut Progress) -> Result<Vec<u8>, Error> {
+        use image::{
+            AnimationDecoder, Frame, ImageError,
+            codecs::gif::{GifDecoder, GifEncoder},
+        };
+        use png::BitDepth;
+        use std::io::Cursor;
+
+        let decoding = |error: ImageError| match error {
+            ImageError::Limits(_) => Error::ImageTooLarge(Stage::Decode),
+            error => Error::CorruptInput(Stage::Decode, error.to_string()),
+        };
+
+        let repeat = gif_repeat(image)?;
+        let frames = GifDecoder::new(Cursor::new(image))
+            .and_then(|decoder| decoder.into_frames().collect_frames())
+            .map_err(decoding)?;
+
+        self.metadata = Metadata::default();
+
+        let start = self.mosh.options.seed;
+        let count = u32::try_from(frames.len()).unwrap_or(u32::MAX);
+        let mut output = Vec::new();
+
+        {
+            // Decoded frames are already composited onto the full canvas
+            let mut encoder = GifEncoder::new_with_speed(&mut output, 10);
+
+            encoder
+                .set_repeat(repeat)
+                .map_err(|error| Error::Encoder(error.to_string()))?;
+
+            for (frame, source) in (0..).zip(frames) {
+                let (left, top, delay) = (source.left(), source.top(), source.delay());
+                let buffer = source.into_buffer();
+                let data = &mut self.mosh.data;
+
+                data.width = buffer.width();
+                data.height = buffer.height();
+                data.line_size = buffer.width() as usize * ColorType::Rgba.samples();
+                data.color_type = ColorType::Rgba;
+                data.bit_depth = BitDepth::Eight;
+                data.palette = None;
+                data.image = buffer.into_raw();
+
+                self.mosh.options.seed = self.frame_seeds.seed(start, frame);
+                self.mosh_buffer(progress, Progress::span(frame, count))?;
+
+                let frame = Frame::from_parts(self.raster()?.into_rgba8(), left, top, delay);
+
+                encoder
+                    .encode_frame(frame)
+                    .map_err(|error| Error::Encoder(error.to_string()))?;
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn encode_apng(&self, frames: &[Vec<u8>], animation: &Animation) -> Result<Vec<u8>, Error> {
+        let mut output = Vec::new();
+        let data = &self.mosh.data;
+        let mut encoder = self.png_encoder(&mut output, data.width, data.height)?;
+
+        encoder.set_animated(animation.frames, animation.loops)?;
+        encoder.set_frame_delay(animation.delay, 1000)?;
+
+        let mut writer = encoder.write_header()?;
+
+        for frame in frames {
+            writer.write_image_data(frame)?;
+        }
+
+        writer.finish()?;
+
+        Ok(output)
+    }
+
+    #[cfg(feature = "gif")]
+    fn encode_gif(
+        &mut self,
+        frames: Vec<Vec<u8>>,
+        animation: &Animation,
+    ) -> Result<Vec<u8>, Error> {
+        use image::{
+            Delay, Frame,
+            codecs::gif::{GifEncoder, Repeat},
+        };
+
+        let mut output = Vec::new();
+
+        {
+            let mut encoder = GifEncoder::new_with_speed(&mut output, 10);
+            let repeat = match animation.loops {
+                0 => Repeat::Infinite,
+                loops => Repeat::Finite(u16::try_from(loops).unwrap_or(u16::MAX)),
+            };
+
+            encoder
+                .set_repeat(repeat)
+                .map_err(|error| Error::Encoder(error.to_string()))?;
+
+            for frame in frames {
+                self.mosh.data.buf = frame;
+
+                let delay = Delay::from_numer_denom_ms(u32::from(animation.delay), 1);
+                let frame = Frame::from_parts(self.raster()?.into_rgba8(), 0, 0, delay);
+
+                encoder
+                    .encode_frame(frame)
+                    .map_err(|error| Error::Encoder(error.to_string()))?;
+            }
+        }
+
+        Ok(output)
+    }
+
+    #[cfg(not(feature = "gif"))]
+    fn encode_gif(&mut self, _: Vec<Vec<u8>>, _: &Animation) -> Result<Vec<u8>, Error> {
+        Err(Error::InvalidOptions(String::from(
+            "GIF support is not enabled",
+        )))
+    }
+}
+
+fn not_animated() -> Error {
+    Error::CorruptInput(Stage::Decode, String::from("Image is not animated"))
+}
+
+/// Loop count of a GIF
+///
+/// `image` reports GIFs without a loop extension as looping forever, while
+/// they play once.
+#[cfg(feature = "gif")]
+fn gif_repeat(image: &[u8]) -> Result<image::codecs::gif::Repeat, Error> {
+    use image::codecs::gif::Repeat;
+    use std::io::Cursor;
+
+    let decoding =
+        |error: gif::DecodingError| Error::CorruptInput(Stage::Decode, error.to_string());
+    let mut decoder = gif::DecodeOptions::new()
+        .read_info(Cursor::new(image))
+        .map_err(decoding)?;
+
+    // The loop extension precedes the first frame
+    decoder.next_frame_info().map_err(decoding)?;
+
+    Ok(match decoder.repeat() {
+        gif::Repeat::Finite(count) => Repeat::Finite(count),
+        gif::Repeat::Infinite => Repeat::Infinite,
+    })
+}
@@ -0,0 +1,110 @@
+use png::{BitDepth, ColorType};
+#[cfg(feature = "js")]
+use wasm_bindgen::{Clamped, prelude::*};
+#[cfg(feature = "js")]
+use web_sys::ImageData;
+
+use libmosh::generate_palette;
+
+use crate::{
+    Core,
+    error::{Error, Stage},
+    metadata::Metadata,
+    progress::Progress,
+};
+
+#[cfg_attr(feature = "js", wasm_bindgen)]
+impl Core {
+    /// Processes raw RGBA pixels in place
+    ///
+    /// Skips image decoding and encoding, so the result can be drawn
+    /// straight to a canvas. Use [`Core::export`] to encode it later.
+    ///
+    /// # Errors
+    ///
+    /// It fails if the image is empty or the buffer length is not
+    /// `width * height * 4`.
+    pub fn mosh_rgba(&mut self, width: u32, height: u32, pixels: &mut [u8]) -> Result<(), Error> {
+        if width == 0 || height == 0 {
+            return Err(Error::CorruptInput(
+                Stage::Decode,
+                format!("Image size {width}x{height} is empty"),
+            ));
+        }
+
+        let size = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|size| size.checked_mul(4))
+            .ok_or(Error::ImageTooLarge(Stage::Decode))?;
+
+        if pixels.len() != size {
+            return Err(Error::CorruptInput(
+                Stage::Decode,
+                format!("Expected {size} bytes of RGBA data, got {}", pixels.len()),
+            ));
+        }
+
+        let data = &mut self.mosh.data;
+
+        data.width = width;
+        data.height = height;
+        data.line_size = width as usize * ColorType::Rgba.samples();
+        data.color_type = ColorType::Rgba;
+        data.bit_depth = BitDepth::Eight;
+        data.palette = None;
+        data.image = pixels.to_vec();
+
+        self.metadata = Metadata::default();
+        self.mosh_buffer(&mut Progress::default(), Progress::span(0, 1))?;
+        self.write_rgba(pixels);
+
+        Ok(())
+    }
+
+    /// Encodes the last processed image in the selected output format
+    ///
+    /// # Errors
+    ///
+    /// It fails if the output image could not be written or its format is not enabled.
+    pub fn export(&self) -> Result<Vec<u8>, Error> {
+        self.encode()
+    }
+}
+
+#[cfg(feature = "js")]
+#[wasm_bindgen]
+impl Core {
+    /// Processes canvas pixels and returns them as new `ImageData`
+    ///
+    /// # Errors
+    ///
+    /// It fails under the same conditions as [`Core::mosh_rgba`].
+    pub fn mosh_image_data(&mut self, image: &ImageData) -> Result<ImageData, Error> {
+        let Clamped(mut pixels) = image.data();
+
+        self.mosh_rgba(image.width(), image.height(), &mut pixels)?;
+
+        ImageData::new_with_u8_clamped_array_and_sh(Clamped(&pixels), image.width(), image.height())
+            .map_err(|error| Error::Encoder(format!("{error:?}")))
+    }
+}
+
+impl Core {
+    /// Copies the processed RGBA buffer, ANSI output is expanded to opaque RGBA
+    fn write_rgba(&self, pixels: &mut [u8]) {
+        let buf = &self.mosh.data.buf;
+
+        if self.mosh.options.ansi {
+            let palette = generate_palette();
+
+            for (pixel, &index) in pixels.chunks_exact_mut(4).zip(buf) {
+                let offset = usize::from(index) * 3;
+
+                pixel[..3].copy_from_slice(palette.get(offset..offset + 3).unwrap_or(&[0, 0, 0]));
+                pixel[3] = u8::MAX;
+            }
+        } else {
+            pixels.copy_from_slice(buf);
+        }
+    }
+}
@@ -0,0 +1,549 @@
+use png::{ColorType, Decoder, Transformations};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "js")]
+use wasm_bindgen::prelude::*;
+
+use libmosh::err::MoshError;
+
+use std::{io::Cursor, iter};
+
+use crate::{Core, error::Error, options::LIMITS};
+
+/// Rectangle in image pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Region of interest, moshing only shows where the mask is set
+///
+/// Rectangles and the mask image are combined. Coordinates refer to the
+/// processed image, a mask image of another size is scaled to fit.
+/// Frames of animated input are masked at their position on the full
+/// canvas.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Mask {
+    /// Masked areas.
+    pub rects: Vec<Rect>,
+    /// Width of the soft mask edge in pixels.
+    pub feather: u32,
+    /// Mosh outside of the mask instead.
+    pub invert: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<MaskImage>,
+}
+
+/// Blend mode of the moshed image over the original
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Screen,
+    Multiply,
+    Difference,
+    Lighten,
+    Darken,
+}
+
+/// How the moshed image is composited over the original
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Blend {
+    pub mode: BlendMode,
+    /// Weight of the blended result in `0.0..=1.0`.
+    pub opacity: f64,
+}
+
+/// Position of an animation frame on its canvas
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Placement {
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    pub frame: Rect,
+}
+
+/// Coverage values of a mask image
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaskImage {
+    width: u32,
+    height: u32,
+    coverage: Vec<u8>,
+}
+
+impl Rect {
+    #[must_use]
+    pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+impl Mask {
+    /// Creates a mask from a grayscale or alpha PNG
+    ///
+    /// The alpha channel is used if the image has one, luminance otherwise.
+    ///
+    /// # Errors
+    ///
+    /// It fails if the image is not a valid PNG.
+    pub fn from_png(image: &[u8]) -> Result<Self, Error> {
+        let decoding = |e: png::DecodingError| Error::decoding(MoshError::from(e));
+
+        let mut decoder = Decoder::new(Cursor::new(image));
+        decoder.set_transformations(Transformations::normalize_to_color8());
+
+        let mut reader = decoder.read_info().map_err(decoding)?;
+        let mut buf = vec![0; reader.output_buffer_size().unwrap_or_default()];
+        let output = reader.next_frame(&mut buf).map_err(decoding)?;
+        let pixels = &buf[..output.buffer_size()];
+
+        let coverage = match output.color_type {
+            ColorType::Grayscale => pixels.to_vec(),
+            ColorType::GrayscaleAlpha => pixels.chunks_exact(2).map(|pixel| pixel[1]).collect(),
+            ColorType::Rgba => pixels.chunks_exact(4).map(|pixel| pixel[3]).collect(),
+            // Palettes are expanded to RGB
+            ColorType::Rgb | ColorType::Indexed => pixels
+                .chunks_exact(3)
+                .map(|pixel| luminance(pixel[0], pixel[1], pixel[2]))
+                .collect(),
+        };
+
+        let mask = Self {
+            image: Some(MaskImage {
+                width: output.width,
+                height: output.height,
+                coverage,
+            }),
+            ..Self::default()
+        };
+        mask.validate()?;
+
+        Ok(mask)
+    }
+
+    /// Returns `true` if the mask covers nothing
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty() && self.image.is_none()
+    }
+
+    /// Checks that the mask image is not empty and matches its size
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        match &self.image {
+            Some(image) if image.width == 0 || image.height == 0 => Err(Error::InvalidOptions(
+                format!("Mask image size {}x{} is empty", image.width, image.height),
+            )),
+            Some(image)
+                if image.coverage.len() as u64
+                    != u64::from(image.width) * u64::from(image.height) =>
+            {
+                Err(Error::InvalidOptions(format!(
+                    "Mask image has {} values, {}x{} expected",
+                    image.coverage.len(),
+                    image.width,
+                    image.height
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Coverage of every pixel in `0..=255`, row by row
+    #[must_use]
+    pub fn coverage(&self, width: u32, height: u32) -> Vec<u8> {
+        let (columns, rows) = (width as usize, height as usize);
+
+        let mut coverage = match &self.image {
+            Some(image) => image.scaled(width, height),
+            None => vec![0; columns * rows],
+        };
+
+        for rect in &self.rects {
+            let left = rect.x.min(width) as usize;
+            let right = rect.x.saturating_add(rect.width).min(width) as usize;
+            let top = rect.y.min(height) as usize;
+            let bottom = rect.y.saturating_add(rect.height).min(height) as usize;
+
+            for row in coverage
+                .chunks_exact_mut(columns.max(1))
+                .take(bottom)
+                .skip(top)
+            {
+                row[left..right].fill(u8::MAX);
+            }
+        }
+
+        if self.feather > 0 && columns > 0 {
+            blur(&mut coverage, columns, self.feather as usize);
+        }
+
+        if self.invert {
+            for value in &mut coverage {
+                *value = u8::MAX - *value;
+            }
+        }
+
+        coverage
+    }
+
+    /// Coverage of a frame on a larger canvas, row by row
+    ///
+    /// The mask is laid over the canvas and sampled at the frame position.
+    #[must_use]
+    pub fn frame_coverage(&self, canvas_width: u32, canvas_height: u32, frame: Rect) -> Vec<u8> {
+        let canvas = &self.coverage(canvas_width, canvas_height);
+        let columns = canvas_width as usize;
+        let right = frame.x.saturating_add(frame.width);
+
+        (frame.y..frame.y.saturating_add(frame.height))
+            .flat_map(|y| {
+                (frame.x..right).map(move |x| {
+                    // Frames always lie within the canvas
+                    let index = (x < canvas_width).then(|| y as usize * columns + x as usize);
+                    index
+                        .and_then(|index| canvas.get(index))
+                        .copied()
+                        .unwrap_or_default()
+                })
+            })
+            .collect()
+    }
+}
+
+impl BlendMode {
+    /// Parses a mode name, e.g. `screen`
+    ///
+    /// # Errors
+    ///
+    /// It fails if the mode is unknown.
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "normal" => Ok(Self::Normal),
+            "screen" => Ok(Self::Screen),
+            "multiply" => Ok(Self::Multiply),
+            "difference" => Ok(Self::Difference),
+            "lighten" => Ok(Self::Lighten),
+            "darken" => Ok(Self::Darken),
+            _ => Err(Error::InvalidOptions(format!("Unknown blend mode: {name}"))),
+        }
+    }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Screen => "screen",
+            Self::Multiply => "multiply",
+            Self::Difference => "difference",
+            Self::Lighten => "lighten",
+            Self::Darken => "darken",
+        }
+    }
+
+    /// Blends a moshed sample over the original one
+    fn apply(self, bottom: u8, top: u8) -> u8 {
+        let (a, b) = (u32::from(bottom), u32::from(top));
+
+        match self {
+            Self::Normal => top,
+            Self::Screen => (255 - ((255 - a) * (255 - b) + 127) / 255) as u8,
+            Self::Multiply => ((a * b + 127) / 255) as u8,
+            Self::Difference => bottom.abs_diff(top),
+            Self::Lighten => bottom.max(top),
+            Self::Darken => bottom.min(top),
+        }
+    }
+}
+
+impl Blend {
+    /// Returns `true` if the moshed image is shown as is
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        self.mode == BlendMode::Normal && self.opacity >= 1.0
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        LIMITS.opacity.check("opacity", self.opacity)
+    }
+}
+
+impl Default for Blend {
+    fn default() -> Self {
+        Self {
+            mode: BlendMode::Normal,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl MaskImage {
+    /// Nearest neighbor scaling
+    fn scaled(&self, width: u32, height: u32) -> Vec<u8> {
+        if (self.width, self.height) == (width, height) {
+            return self.coverage.clone();
+        }
+
+        // Empty images cover nothing
+        if self.width == 0 || self.height == 0 {
+            return vec![0; width as usize * height as usize];
+        }
+
+        let source_x = |x: u32| (u64::from(x) * u64::from(self.width) / u64::from(width)) as usize;
+        let source_y =
+            |y: u32| (u64::from(y) * u64::from(self.height) / u64::from(height)) as usize;
+
+        (0..height)
+            .flat_map(|y| {
+                let row = source_y(y) * self.width as usize;
+                (0..width).map(move |x| self.coverage[row + source_x(x)])
+            })
+            .collect()
+    }
+}
+
+#[cfg_attr(feature = "js", wasm_bindgen)]
+impl Core {
+    /// Restricts moshing to a rectangle, in addition to earlier ones
+    pub fn add_mask_rect(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        self.mask
+            .get_or_insert_default()
+            .rects
+            .push(Rect::new(x, y, width, height));
+    }
+
+    /// Restricts moshing to a grayscale or alpha PNG mask, keeping rectangles
+    ///
+    /// # Errors
+    ///
+    /// It fails if the mask is not a valid PNG.
+    pub fn set_mask_png(&mut self, image: &[u8]) -> Result<(), Error> {
+        let mask = self.mask.get_or_insert_default();
+        mask.image = Mask::from_png(image)?.image;
+
+        Ok(())
+    }
+
+    /// Sets the width of the soft mask edge in pixels
+    pub fn set_mask_feather(&mut self, radius: u32) {
+        self.mask.get_or_insert_default().feather = radius;
+    }
+
+    /// Moshes outside of the mask instead
+    pub fn set_mask_invert(&mut self, value: bool) {
+        self.mask.get_or_insert_default().invert = value;
+    }
+
+    /// Moshes the whole image again
+    pub fn clear_mask(&mut self) {
+        self.mask = None;
+    }
+
+    /// Returns the blend mode: `normal`, `screen`, `multiply`, `difference`,
+    /// `lighten` or `darken`
+    #[cfg_attr(feature = "js", wasm_bindgen(unchecked_return_type = "BlendMode"))]
+    pub fn blend_mode(&self) -> String {
+        self.blend.mode.name().to_string()
+    }
+
+    /// Selects how the moshed image is blended over the original
+    ///
+    /// Palette and ANSI output can not be blended, pixels are picked from
+    /// either side by coverage and opacity instead.
+    ///
+    /// # Errors
+    ///
+    /// It fails if the mode is unknown.
+    pub fn set_blend_mode(&mut self, mode: &str) -> Result<(), Error> {
+        self.blend.mode = BlendMode::from_name(mode)?;
+
+        Ok(())
+    }
+
+    /// Returns the opacity of the moshed image in `0.0..=1.0`
+    pub fn opacity(&self) -> f64 {
+        self.blend.opacity
+    }
+
+    /// Sets the opacity of the moshed image, `0.0` keeps the original
+    ///
+    /// # Errors
+    ///
+    /// It fails if the value is outside of `0.0..=1.0`.
+    pub fn set_opacity(&mut self, value: f64) -> Result<(), Error> {
+        self.set_blend(Blend {
+            opacity: value,
+            ..self.blend
+        })
+    }
+}
+
+impl Core {
+    #[must_use]
+    pub fn mask(&self) -> Option<&Mask> {
+        self.mask.as_ref()
+    }
+
+    /// Restricts moshing to a region, `None` moshes the whole image
+    pub fn set_mask(&mut self, mask: Option<Mask>) {
+        self.mask = mask;
+    }
+
+    #[must_use]
+    pub const fn blend(&self) -> Blend {
+        self.blend
+    }
+
+    /// Sets the blend mode and opacity of the moshed image
+    ///
+    /// # Errors
+    ///
+    /// It fails if the opacity is outside of `0.0..=1.0`.
+    pub fn set_blend(&mut self, blend: Blend) -> Result<(), Error> {
+        blend.validate()?;
+        self.blend = blend;
+
+        Ok(())
+    }
+
+    /// Blends the moshed buffer over the original image
+    ///
+    /// The mask coverage and opacity weigh the blended result.
+    pub(crate) fn composite(&mut self) -> Result<(), Error> {
+        let mask = self.mask.as_ref().filter(|mask| !mask.is_empty());
+
+        if let Some(mask) = mask {
+            mask.validate()?;
+        }
+
+        if mask.is_none() && self.blend.is_identity() {
+            return Ok(());
+        }
+
+        let data = &self.mosh.data;
+        let coverage = match (mask, self.placement) {
+            (Some(mask), Some(placement)) => mask.frame_coverage(
+                placement.canvas_width,
+                placement.canvas_height,
+                placement.frame,
+            ),
+            (Some(mask), None) => mask.coverage(data.width, data.height),
+            (None, _) => vec![u8::MAX; data.width as usize * data.height as usize],
+        };
+
+        // ANSI output holds palette indices, the original is converted to match
+        let original = if self.mosh.options.ansi {
+            let mut ansi = data.clone();
+            ansi.buf = std::mem::take(&mut ansi.image);
+            ansi.generate_ansi_data().map_err(Error::moshing)?;
+            ansi.buf
+        } else {
+            data.image.clone()
+        };
+
+        // Indices can not be blended, each pixel is picked from one side
+        let (samples, pick) = if self.mosh.options.ansi || data.color_type == ColorType::Indexed {
+            (1, true)
+        } else {
+            (data.color_type.samples(), false)
+        };
+
+        // Transparency is mixed but never blended
+        let alpha_sample = match data.color_type {
+            ColorType::GrayscaleAlpha | ColorType::Rgba => Some(samples - 1),
+            _ => None,
+        };
+
+        let Blend { mode, opacity } = self.blend;
+        let opacity = (opacity * 255.0).round() as u32;
+
+        let buf = &mut self.mosh.data.buf;
+        let pixels = buf
+            .chunks_exact_mut(samples)
+            .zip(original.chunks_exact(samples))
+            .zip(coverage);
+
+        for ((pixel, source), coverage) in pixels {
+            let alpha = ((u32::from(coverage) * opacity + 127) / 255) as u8;
+
+            if pick {
+                if alpha < 128 {
+                    pixel.copy_from_slice(source);
+                }
+            } else {
+                for (index, (value, &source)) in pixel.iter_mut().zip(source).enumerate() {
+                    let top = if Some(index) == alpha_sample {
+                        *value
+                    } else {
+                        mode.apply(source, *value)
+                    };
+
+                    *value = mix(source, top, alpha);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Mixes two samples, `alpha` is the weight of `top`
+fn mix(bottom: u8, top: u8, alpha: u8) -> u8 {
+    let alpha = u32::from(alpha);
+    let value = u32::from(bottom) * (255 - alpha) + u32::from(top) * alpha;
+
+    ((value + 127) / 255) as u8
+}
+
+fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    ((u32::from(r) * 299 + u32::from(g) * 587 + u32::from(b) * 114) / 1000) as u8
+}
+
+/// Box blur over rows, then columns
+fn blur(values: &mut [u8], width: usize, radius: usize) {
+    let height = values.len() / width;
+
+    for row in values.chunks_exact_mut(width) {
+        blur_line(row, radius);
+    }
+
+    let mut column = vec![0; height];
+
+    for x in 0..width {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = values[y * width + x];
+        }
+
+        blur_line(&mut column, radius);
+
+        for (y, value) in column.iter().enumerate() {
+            values[y * width + x] = *value;
+        }
+    }
+}
+
+/// Averages every value with its neighbors within `radius`
+fn blur_line(line: &mut [u8], radius: usize) {
+    let sums: Vec<u32> = iter::once(0)
+        .chain(line.iter().scan(0, |sum, &value| {
+            *sum += u32::from(value);
+            Some(*sum)
+        }))
+        .collect();
+
+    for (index, value) in line.iter_mut().enumerate() {
+        let start = index.saturating_sub(radius);
+        let end = (index + radius + 1).min(sums.len() - 1);
+
+        *value = ((sums[end] - sums[start]) / (end - start) as u32) as u8;
+    }
+}
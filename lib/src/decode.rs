@@ -0,0 +1,219 @@
+use png::{BitDepth, ColorType, Decoder, Reader, Transformations};
+
+use libmosh::{MoshCore, err::MoshError};
+
+use std::io::Cursor;
+
+use crate::error::{Error, Stage};
+
+/// Input image formats
+///
+/// Formats other than PNG require the matching cargo feature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Webp,
+    Gif,
+    Bmp,
+    Qoi,
+}
+
+impl Format {
+    /// Detects the image format from its signature
+    #[must_use]
+    pub fn detect(input: &[u8]) -> Option<Self> {
+        match input {
+            [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, ..] => Some(Self::Png),
+            [0xff, 0xd8, 0xff, ..] => Some(Self::Jpeg),
+            [
+                b'R',
+                b'I',
+                b'F',
+                b'F',
+                _,
+                _,
+                _,
+                _,
+                b'W',
+                b'E',
+                b'B',
+                b'P',
+                ..,
+            ] => Some(Self::Webp),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(Self::Gif),
+            [b'B', b'M', ..] => Some(Self::Bmp),
+            [b'q', b'o', b'i', b'f', ..] => Some(Self::Qoi),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Png => "PNG",
+            Self::Jpeg => "JPEG",
+            Self::Webp => "WebP",
+            Self::Gif => "GIF",
+            Self::Bmp => "BMP",
+            Self::Qoi => "QOI",
+        }
+    }
+
+    /// Returns `true` if the format can be decoded by this build
+    #[must_use]
+    pub const fn is_enabled(self) -> bool {
+        match self {
+            Self::Png => true,
+            Self::Jpeg => cfg!(feature = "jpeg"),
+            Self::Webp => cfg!(feature = "webp"),
+            Self::Gif => cfg!(feature = "gif"),
+            Self::Bmp => cfg!(feature = "bmp"),
+            Self::Qoi => cfg!(feature = "qoi"),
+        }
+    }
+}
+
+/// Returns `true` if the image is an animated APNG or GIF
+#[must_use]
+pub fn is_animated(input: &[u8]) -> bool {
+    match Format::detect(input) {
+        Some(Format::Png) => Decoder::new(Cursor::new(input))
+            .read_info()
+            .is_ok_and(|reader| reader.info().animation_control.is_some()),
+        #[cfg(feature = "gif")]
+        Some(Format::Gif) => {
+            use image::{AnimationDecoder, codecs::gif::GifDecoder};
+
+            GifDecoder::new(Cursor::new(input))
+                .is_ok_and(|decoder| decoder.into_frames().take(2).count() > 1)
+        }
+        _ => false,
+    }
+}
+
+/// Decodes any supported image into the core buffer
+pub(crate) fn read_image(core: &mut MoshCore, input: &[u8]) -> Result<Format, Error> {
+    match Format::detect(input) {
+        Some(Format::Png) | None => {
+            read_png(core, input)?;
+
+            Ok(Format::Png)
+        }
+        Some(format) if format.is_enabled() => {
+            #[cfg(any(
+                feature = "bmp",
+                feature = "gif",
+                feature = "jpeg",
+                feature = "qoi",
+                feature = "webp"
+            ))]
+            read_raster(core, input, format)?;
+
+            Ok(format)
+        }
+        Some(format) => Err(Error::CorruptInput(
+            Stage::Decode,
+            format!("{} support is not enabled", format.name()),
+        )),
+    }
+}
+
+/// Reads a PNG header, other bit depths are expanded to 8 bits
+///
+/// 8-bit images are decoded as they are, so indexed ones keep their palette.
+pub(crate) fn png_reader(input: &[u8]) -> Result<Reader<Cursor<&[u8]>>, Error> {
+    let decoding = |e: png::DecodingError| Error::decoding(MoshError::from(e));
+    let reader = Decoder::new(Cursor::new(input))
+        .read_info()
+        .map_err(decoding)?;
+
+    if reader.info().bit_depth == BitDepth::Eight {
+        return Ok(reader);
+    }
+
+    let mut decoder = Decoder::new(Cursor::new(input));
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    decoder.read_info().map_err(decoding)
+}
+
+fn read_png(core: &mut MoshCore, input: &[u8]) -> Result<(), Error> {
+    let mut reader = png_reader(input)?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or(Error::ImageTooLarge(Stage::Decode))?;
+    let mut buf = vec![0_u8; size];
+    let output = reader
+        .next_frame(&mut buf)
+        .map_err(|e| Error::decoding(MoshError::from(e)))?;
+
+    buf.truncate(output.buffer_size());
+
+    let data = &mut core.data;
+
+    data.width = output.width;
+    data.height = output.height;
+    data.line_size = output.line_size;
+    data.color_type = output.color_type;
+    data.bit_depth = output.bit_depth;
+    // Expanded palettes are not used anymore
+    data.palette = match output.color_type {
+        ColorType::Indexed => reader
+            .info()
+            .palette
+            .as_ref()
+            .map(|palette| palette.to_vec()),
+        _ => None,
+    };
+    data.buf.clone_from(&buf);
+    data.image = buf;
+
+    Ok(())
+}
+
+#[cfg(any(
+    feature = "bmp",
+    feature = "gif",
+    feature = "jpeg",
+    feature = "qoi",
+    feature = "webp"
+))]
+fn read_raster(core: &mut MoshCore, input: &[u8], format: Format) -> Result<(), Error> {
+    use image::{DynamicImage, ImageError, ImageFormat};
+
+    let image_format = match format {
+        Format::Png => ImageFormat::Png,
+        Format::Jpeg => ImageFormat::Jpeg,
+        Format::Webp => ImageFormat::WebP,
+        Format::Gif => ImageFormat::Gif,
+        Format::Bmp => ImageFormat::Bmp,
+        Format::Qoi => ImageFormat::Qoi,
+    };
+
+    let image =
+        image::load_from_memory_with_format(input, image_format).map_err(|error| match error {
+            ImageError::Limits(_) => Error::ImageTooLarge(Stage::Decode),
+            error => Error::CorruptInput(Stage::Decode, error.to_string()),
+        })?;
+
+    let (width, height, color) = (image.width(), image.height(), image.color());
+    let (color_type, buf) = match image {
+        DynamicImage::ImageLuma8(buf) => (ColorType::Grayscale, buf.into_raw()),
+        DynamicImage::ImageLumaA8(buf) => (ColorType::GrayscaleAlpha, buf.into_raw()),
+        image if color.has_alpha() => (ColorType::Rgba, image.into_rgba8().into_raw()),
+        image => (ColorType::Rgb, image.into_rgb8().into_raw()),
+    };
+
+    let data = &mut core.data;
+
+    data.width = width;
+    data.height = height;
+    data.line_size = width as usize * color_type.samples();
+    data.color_type = color_type;
+    data.bit_depth = BitDepth::Eight;
+    data.palette = None;
+    data.buf.clone_from(&buf);
+    data.image = buf;
+
+    Ok(())
+}
@@ -0,0 +1,405 @@
+use png::{BitDepth, ColorType, Compression, Filter, Info};
+use serde::{Deserialize, Serialize};
+
+use libmosh::generate_palette;
+
+use crate::{Core, decode::Format, error::Error, options::LIMITS, recipe::RECIPE_KEYWORD};
+
+/// Default JPEG quality
+pub const JPEG_QUALITY: u8 = 90;
+
+/// Lossy WebP needs libwebp, which is not built for WebAssembly
+const LOSSY_WEBP: bool = cfg!(all(feature = "webp-lossy", not(target_arch = "wasm32")));
+
+/// Output image encoders
+///
+/// Formats other than PNG require the matching cargo feature.
+/// Metadata and recipes are only written to PNG output.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(try_from = "OutputSettings", into = "OutputSettings")]
+pub enum OutputFormat {
+    /// Lossless PNG, `filter` overrides the compression preset.
+    Png {
+        compression: Compression,
+        filter: Option<Filter>,
+    },
+    /// Lossy JPEG with quality in `1..=100`, alpha is dropped.
+    Jpeg {
+        quality: u8,
+    },
+    /// WebP, lossless without quality.
+    ///
+    /// Lossy output with quality in `1..=100` requires the `webp-lossy`
+    /// feature and is not available on WebAssembly targets.
+    Webp {
+        quality: Option<u8>,
+    },
+    Qoi,
+}
+
+impl OutputFormat {
+    #[must_use]
+    pub const fn format(self) -> Format {
+        match self {
+            Self::Png { .. } => Format::Png,
+            Self::Jpeg { .. } => Format::Jpeg,
+            Self::Webp { .. } => Format::Webp,
+            Self::Qoi => Format::Qoi,
+        }
+    }
+
+    #[must_use]
+    pub const fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png { .. } => "image/png",
+            Self::Jpeg { .. } => "image/jpeg",
+            Self::Webp { .. } => "image/webp",
+            Self::Qoi => "image/qoi",
+        }
+    }
+
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Png { .. } => "png",
+            Self::Jpeg { .. } => "jpg",
+            Self::Webp { .. } => "webp",
+            Self::Qoi => "qoi",
+        }
+    }
+
+    /// Fails if the format is not enabled or the quality is out of range
+    pub(crate) fn check(self) -> Result<Self, Error> {
+        if !self.format().is_enabled() {
+            return Err(Error::InvalidOptions(format!(
+                "{} support is not enabled",
+                self.format().name()
+            )));
+        }
+
+        match self {
+            Self::Webp { quality: Some(_) } if !LOSSY_WEBP => Err(Error::InvalidOptions(
+                String::from("Lossy WebP support is not enabled"),
+            )),
+            Self::Jpeg { quality }
+            | Self::Webp {
+                quality: Some(quality),
+            } => LIMITS.quality.check("quality", quality),
+            _ => Ok(()),
+        }?;
+
+        Ok(self)
+    }
+}
+
+/// Serialized [`OutputFormat`] with named compression and filter
+#[derive(PartialEq, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "lowercase", deny_unknown_fields)]
+enum OutputSettings {
+    Png {
+        compression: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        filter: Option<String>,
+    },
+    Jpeg {
+        quality: u8,
+    },
+    Webp {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        quality: Option<u8>,
+    },
+    Qoi,
+}
+
+impl From<OutputFormat> for OutputSettings {
+    fn from(output: OutputFormat) -> Self {
+        match output {
+            OutputFormat::Png {
+                compression,
+                filter,
+            } => Self::Png {
+                compression: compression_name(compression).to_string(),
+                filter: filter.map(|filter| filter_name(filter).to_string()),
+            },
+            OutputFormat::Jpeg { quality } => Self::Jpeg { quality },
+            OutputFormat::Webp { quality } => Self::Webp { quality },
+            OutputFormat::Qoi => Self::Qoi,
+        }
+    }
+}
+
+impl TryFrom<OutputSettings> for OutputFormat {
+    type Error = Error;
+
+    fn try_from(settings: OutputSettings) -> Result<Self, Error> {
+        Ok(match settings {
+            OutputSettings::Png {
+                compression,
+                filter,
+            } => Self::Png {
+                compression: compression_from_name(&compression)?,
+                filter: filter.as_deref().map(filter_from_name).transpose()?,
+            },
+            OutputSettings::Jpeg { quality } => Self::Jpeg { quality },
+            OutputSettings::Webp { quality } => Self::Webp { quality },
+            OutputSettings::Qoi => Self::Qoi,
+        })
+    }
+}
+
+// PNG settings do not implement `PartialEq`, their names are compared
+impl PartialEq for OutputFormat {
+    fn eq(&self, other: &Self) -> bool {
+        OutputSettings::from(*self) == OutputSettings::from(*other)
+    }
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        Self::Png {
+            compression: Compression::Balanced,
+            filter: None,
+        }
+    }
+}
+
+pub(crate) fn compression_from_name(name: &str) -> Result<Compression, Error> {
+    match name {
+        "none" => Ok(Compression::NoCompression),
+        "fastest" => Ok(Compression::Fastest),
+        "fast" => Ok(Compression::Fast),
+        "balanced" => Ok(Compression::Balanced),
+        "high" => Ok(Compression::High),
+        _ => Err(Error::InvalidOptions(format!(
+            "Unknown compression level: {name}"
+        ))),
+    }
+}
+
+pub(crate) const fn compression_name(compression: Compression) -> &'static str {
+    match compression {
+        Compression::NoCompression => "none",
+        Compression::Fastest => "fastest",
+        Compression::Fast => "fast",
+        Compression::High => "high",
+        _ => "balanced",
+    }
+}
+
+pub(crate) fn filter_from_name(name: &str) -> Result<Filter, Error> {
+    match name {
+        "none" => Ok(Filter::NoFilter),
+        "sub" => Ok(Filter::Sub),
+        "up" => Ok(Filter::Up),
+        "avg" => Ok(Filter::Avg),
+        "paeth" => Ok(Filter::Paeth),
+        "adaptive" => Ok(Filter::Adaptive),
+        _ => Err(Error::InvalidOptions(format!("Unknown filter: {name}"))),
+    }
+}
+
+pub(crate) const fn filter_name(filter: Filter) -> &'static str {
+    match filter {
+        Filter::NoFilter => "none",
+        Filter::Sub => "sub",
+        Filter::Up => "up",
+        Filter::Avg => "avg",
+        Filter::Paeth => "paeth",
+        _ => "adaptive",
+    }
+}
+
+impl Core {
+    /// Encodes the processed buffer in the selected output format
+    pub(crate) fn encode(&self) -> Result<Vec<u8>, Error> {
+        match self.output.check()? {
+            OutputFormat::Png { .. } => self.encode_png(),
+            #[cfg(any(feature = "jpeg", feature = "qoi", feature = "webp"))]
+            output => self.encode_raster(output),
+            #[cfg(not(any(feature = "jpeg", feature = "qoi", feature = "webp")))]
+            _ => unreachable!("Disabled formats are rejected above"),
+        }
+    }
+
+    fn encode_png(&self) -> Result<Vec<u8>, Error> {
+        let mut output: Vec<u8> = Vec::new();
+        let data = &self.mosh.data;
+        let mut writer = self
+            .png_encoder(&mut output, data.width, data.height)?
+            .write_header()?;
+
+        writer.write_image_data(&self.mosh.data.buf)?;
+        writer.finish()?;
+
+        Ok(output)
+    }
+
+    /// Creates a PNG encoder matching the processed buffer
+    pub(crate) fn png_encoder<'a>(
+        &self,
+        output: &'a mut Vec<u8>,
+        width: u32,
+        height: u32,
+    ) -> Result<png::Encoder<'static, &'a mut Vec<u8>>, Error> {
+        let data = &self.mosh.data;
+        let ansi = self.mosh.options.ansi;
+
+        let mut info = Info::with_size(width, height);
+        self.metadata.apply(&mut info, self.metadata_filter);
+
+        let mut encoder = png::Encoder::with_info(output, info)?;
+
+        encoder.set_color(if ansi {
+            ColorType::Indexed
+        } else {
+            data.color_type
+        });
+
+        encoder.set_depth(if ansi {
+            BitDepth::Eight
+        } else {
+            data.bit_depth
+        });
+
+        if let OutputFormat::Png {
+            compression,
+            filter,
+        } = self.output
+        {
+            encoder.set_compression(compression);
+
+            if let Some(filter) = filter {
+                encoder.set_filter(filter);
+            }
+        }
+
+        if ansi {
+            encoder.set_palette(generate_palette());
+        } else if data.color_type == ColorType::Indexed
+            && let Some(palette) = &data.palette
+        {
+            encoder.set_palette(palette.clone());
+        }
+
+        if self.embed_recipe {
+            encoder.add_itxt_chunk(RECIPE_KEYWORD.to_string(), self.recipe().to_json())?;
+        }
+
+        Ok(encoder)
+    }
+
+    /// Expands the processed buffer to 8-bit grayscale or RGB(A)
+    #[cfg(any(feature = "gif", feature = "jpeg", feature = "qoi", feature = "webp"))]
+    pub(crate) fn raster(&self) -> Result<image::DynamicImage, Error> {
+        use image::{DynamicImage, ImageBuffer};
+
+        let data = &self.mosh.data;
+        let palette = if self.mosh.options.ansi {
+            Some(generate_palette())
+        } else {
+            data.palette.clone()
+        };
+
+        let (color_type, buf) = if self.mosh.options.ansi || data.color_type == ColorType::Indexed {
+            let palette = palette
+                .ok_or_else(|| Error::Encoder(String::from("Indexed image has no palette")))?;
+
+            let buf = data
+                .buf
+                .iter()
+                .flat_map(|&index| {
+                    let offset = usize::from(index) * 3;
+                    palette
+                        .get(offset..offset + 3)
+                        .unwrap_or(&[0, 0, 0])
+                        .to_vec()
+                })
+                .collect();
+
+            (ColorType::Rgb, buf)
+        } else {
+            (data.color_type, data.buf.clone())
+        };
+
+        let invalid = || Error::Encoder(String::from("Invalid buffer size"));
+        let (width, height) = (data.width, data.height);
+        let image = match color_type {
+            ColorType::Grayscale => DynamicImage::ImageLuma8(
+                ImageBuffer::from_raw(width, height, buf).ok_or_else(invalid)?,
+            ),
+            ColorType::GrayscaleAlpha => DynamicImage::ImageLumaA8(
+                ImageBuffer::from_raw(width, height, buf).ok_or_else(invalid)?,
+            ),
+            ColorType::Rgba => DynamicImage::ImageRgba8(
+                ImageBuffer::from_raw(width, height, buf).ok_or_else(invalid)?,
+            ),
+            _ => DynamicImage::ImageRgb8(
+                ImageBuffer::from_raw(width, height, buf).ok_or_else(invalid)?,
+            ),
+        };
+
+        Ok(image)
+    }
+
+    #[cfg(any(feature = "jpeg", feature = "qoi", feature = "webp"))]
+    fn encode_raster(&self, output_format: OutputFormat) -> Result<Vec<u8>, Error> {
+        #[cfg(any(feature = "jpeg", feature = "qoi"))]
+        use image::DynamicImage;
+
+        let image = self.raster()?;
+        let mut output = Vec::new();
+        let encoded = match output_format {
+            #[cfg(feature = "jpeg")]
+            OutputFormat::Jpeg { quality } => {
+                let image = if image.color().has_color() {
+                    DynamicImage::ImageRgb8(image.into_rgb8())
+                } else {
+                    DynamicImage::ImageLuma8(image.into_luma8())
+                };
+
+                image.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+                    &mut output,
+                    quality,
+                ))
+            }
+            #[cfg(all(feature = "webp-lossy", not(target_arch = "wasm32")))]
+            OutputFormat::Webp {
+                quality: Some(quality),
+            } => return encode_lossy_webp(image, quality),
+            #[cfg(feature = "webp")]
+            OutputFormat::Webp { .. } => image
+                .write_with_encoder(image::codecs::webp::WebPEncoder::new_lossless(&mut output)),
+            #[cfg(feature = "qoi")]
+            OutputFormat::Qoi => {
+                let image = if image.color().has_alpha() {
+                    DynamicImage::ImageRgba8(image.into_rgba8())
+                } else {
+                    DynamicImage::ImageRgb8(image.into_rgb8())
+                };
+
+                image.write_with_encoder(image::codecs::qoi::QoiEncoder::new(&mut output))
+            }
+            _ => unreachable!("Disabled formats are rejected before encoding"),
+        };
+
+        encoded.map_err(|error| Error::Encoder(error.to_string()))?;
+
+        Ok(output)
+    }
+}
+
+#[cfg(all(feature = "webp-lossy", not(target_arch = "wasm32")))]
+fn encode_lossy_webp(image: image::DynamicImage, quality: u8) -> Result<Vec<u8>, Error> {
+    let (width, height) = (image.width(), image.height());
+    let encoded = if image.color().has_alpha() {
+        let pixels = image.into_rgba8();
+        libwebp::Encoder::from_rgba(&pixels, width, height).encode_simple(false, quality.into())
+    } else {
+        let pixels = image.into_rgb8();
+        libwebp::Encoder::from_rgb(&pixels, width, height).encode_simple(false, quality.into())
+    };
+
+    encoded
+        .map(|output| output.to_vec())
+        .map_err(|error| Error::Encoder(format!("WebP encoding failed: {error:?}")))
+}
@@ -0,0 +1,197 @@
+#[cfg(feature = "js")]
+use wasm_bindgen::prelude::*;
+
+use libmosh::err::MoshError;
+
+use std::{
+    fmt::{self, Display},
+    io,
+};
+
+/// Processing stage an error originated from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Decode,
+    Mosh,
+    Encode,
+    Options,
+}
+
+/// Error category exposed to JavaScript as `kind`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    CorruptInput,
+    ImageTooLarge,
+    Encoder,
+    InvalidOptions,
+    Cancelled,
+}
+
+/// Processing errors
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum Error {
+    /// Input is truncated or not a valid image.
+    CorruptInput(Stage, String),
+    /// Image exceeds decoder or memory limits.
+    ImageTooLarge(Stage),
+    /// Output image could not be written.
+    Encoder(String),
+    /// Options are malformed or out of range.
+    InvalidOptions(String),
+    /// Processing was cancelled before the stage.
+    Cancelled(Stage),
+}
+
+/// Error object thrown to JavaScript callers
+#[cfg_attr(feature = "js", wasm_bindgen)]
+pub struct CoreError {
+    kind: ErrorKind,
+    stage: Stage,
+    message: String,
+}
+
+impl Stage {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Decode => "decode",
+            Self::Mosh => "mosh",
+            Self::Encode => "encode",
+            Self::Options => "options",
+        }
+    }
+}
+
+impl ErrorKind {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::CorruptInput => "corrupt-input",
+            Self::ImageTooLarge => "image-too-large",
+            Self::Encoder => "encoder",
+            Self::InvalidOptions => "invalid-options",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl Error {
+    #[must_use]
+    pub const fn kind(&self) -> ErrorKind {
+        match self {
+            Self::CorruptInput(..) => ErrorKind::CorruptInput,
+            Self::ImageTooLarge(_) => ErrorKind::ImageTooLarge,
+            Self::Encoder(_) => ErrorKind::Encoder,
+            Self::InvalidOptions(_) => ErrorKind::InvalidOptions,
+            Self::Cancelled(_) => ErrorKind::Cancelled,
+        }
+    }
+
+    #[must_use]
+    pub const fn stage(&self) -> Stage {
+        match self {
+            Self::CorruptInput(stage, _) | Self::ImageTooLarge(stage) | Self::Cancelled(stage) => {
+                *stage
+            }
+            Self::Encoder(_) => Stage::Encode,
+            Self::InvalidOptions(_) => Stage::Options,
+        }
+    }
+
+    pub(crate) fn decoding(error: MoshError) -> Self {
+        Self::from_mosh(Stage::Decode, error)
+    }
+
+    pub(crate) fn moshing(error: MoshError) -> Self {
+        Self::from_mosh(Stage::Mosh, error)
+    }
+
+    fn from_mosh(stage: Stage, error: MoshError) -> Self {
+        match error {
+            MoshError::DecodingError(png::DecodingError::LimitsExceeded)
+            | MoshError::OutOfMemory => Self::ImageTooLarge(stage),
+            MoshError::DecodingError(png::DecodingError::IoError(error))
+                if error.kind() == io::ErrorKind::UnexpectedEof =>
+            {
+                Self::CorruptInput(stage, String::from("Unexpected end of image data"))
+            }
+            error => Self::CorruptInput(stage, error.to_string()),
+        }
+    }
+}
+
+#[cfg_attr(feature = "js", wasm_bindgen)]
+impl CoreError {
+    #[cfg_attr(
+        feature = "js",
+        wasm_bindgen(getter, unchecked_return_type = "ErrorKind")
+    )]
+    pub fn kind(&self) -> String {
+        self.kind.as_str().to_string()
+    }
+
+    #[cfg_attr(feature = "js", wasm_bindgen(getter, unchecked_return_type = "Stage"))]
+    pub fn stage(&self) -> String {
+        self.stage.as_str().to_string()
+    }
+
+    #[cfg_attr(feature = "js", wasm_bindgen(getter))]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    #[cfg_attr(feature = "js", wasm_bindgen(js_name = toString))]
+    pub fn to_js_string(&self) -> String {
+        format!("{} ({}): {}", self.kind, self.stage, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CorruptInput(_, message)
+            | Self::Encoder(message)
+            | Self::InvalidOptions(message) => f.write_str(message),
+            Self::ImageTooLarge(_) => f.write_str("Image is too large"),
+            Self::Cancelled(_) => f.write_str("Processing was cancelled"),
+        }
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Self {
+        Self::Encoder(e.to_string())
+    }
+}
+
+impl From<&Error> for CoreError {
+    fn from(e: &Error) -> Self {
+        Self {
+            kind: e.kind(),
+            stage: e.stage(),
+            message: e.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "js")]
+impl From<Error> for JsValue {
+    fn from(e: Error) -> Self {
+        CoreError::from(&e).into()
+    }
+}
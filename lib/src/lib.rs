@@ -1,139 +1,713 @@
-use png::{BitDepth, ColorType};
+#[cfg(feature = "js")]
 use wasm_bindgen::prelude::*;
 
-use libmosh::{MoshCore, generate_palette};
-
+use libmosh::{MoshCore, MoshData, MoshOptions};
+
+use std::ops::Range;
+
+pub use animation::{Animation, AnimationFormat, FrameSeeds};
+pub use composite::{Blend, BlendMode, Mask, Rect};
+pub use decode::Format;
+pub use encode::OutputFormat;
+pub use error::{CoreError, Error, ErrorKind, Stage};
+pub use metadata::{Chunk, Metadata, MetadataFilter};
+pub use options::{LIMITS, Limit, Limits, Options};
+pub use pipeline::{Pass, Pipeline};
+pub use progress::{CancelToken, Progress};
+pub use recipe::Recipe;
+pub use seed::{SeedSequence, seed_from_phrase};
+#[cfg(feature = "js")]
+pub use worker::{AsyncCore, start_worker};
+
+pub mod animation;
+mod canvas;
+pub mod composite;
+pub mod decode;
+pub mod encode;
+pub mod error;
+pub mod metadata;
+pub mod options;
+pub mod pipeline;
+pub mod progress;
+pub mod recipe;
+pub mod seed;
+#[cfg(feature = "js")]
+mod typescript;
 pub mod utils;
+#[cfg(feature = "js")]
+pub mod worker;
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "js", wasm_bindgen)]
 #[derive(Default)]
-pub struct Core(MoshCore);
+pub struct Core {
+    mosh: MoshCore,
+    embed_recipe: bool,
+    metadata: Metadata,
+    metadata_filter: MetadataFilter,
+    output: OutputFormat,
+    frame_seeds: FrameSeeds,
+    mask: Option<Mask>,
+    blend: Blend,
+    /// Position of the APNG frame being processed.
+    placement: Option<composite::Placement>,
+    source: Option<Source>,
+}
 
-#[wasm_bindgen]
+// Native users may move a `Core` to another thread
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Core>();
+};
+
+/// Decoded input kept by [`Core::load`]
+enum Source {
+    Still {
+        data: MoshData,
+        metadata: Box<Metadata>,
+    },
+    /// Animated input is decoded again on every run.
+    Animated(Vec<u8>),
+}
+
+#[cfg_attr(feature = "js", wasm_bindgen)]
 impl Core {
+    /// Returns `true` if output is converted to the ANSI color palette
     pub fn ansi(&self) -> bool {
-        self.0.options.ansi
+        self.mosh.options.ansi
     }
+
+    /// Returns the minimum amount of chunks to process
     pub fn min_rate(&self) -> u16 {
-        self.0.options.min_rate
+        self.mosh.options.min_rate
     }
 
+    /// Returns the maximum amount of chunks to process
     pub fn max_rate(&self) -> u16 {
-        self.0.options.max_rate
+        self.mosh.options.max_rate
     }
 
+    /// Returns the pixelation intensity, `1` is off
     pub fn pixelation(&self) -> u8 {
-        self.0.options.pixelation
+        self.mosh.options.pixelation
     }
 
+    /// Returns the chance of line shift in `0..=1`
     pub fn line_shift(&self) -> f64 {
-        self.0.options.line_shift
+        self.mosh.options.line_shift
     }
 
+    /// Returns the chance of reverse in `0..=1`
     pub fn reverse(&self) -> f64 {
-        self.0.options.reverse
+        self.mosh.options.reverse
     }
 
+    /// Returns the chance of flip in `0..=1`
     pub fn flip(&self) -> f64 {
-        self.0.options.flip
+        self.mosh.options.flip
     }
 
+    /// Returns the chance of channel swap in `0..=1`
     pub fn channel_swap(&self) -> f64 {
-        self.0.options.channel_swap
+        self.mosh.options.channel_swap
     }
 
+    /// Returns the chance of channel shift in `0..=1`
     pub fn channel_shift(&self) -> f64 {
-        self.0.options.channel_shift
+        self.mosh.options.channel_shift
     }
 
+    /// Returns the random seed, a `bigint` in JavaScript
     pub fn seed(&self) -> u64 {
-        self.0.options.seed
+        self.mosh.options.seed
     }
 
+    /// Returns `true` if settings are stored in the output image metadata
+    pub fn embed_recipe(&self) -> bool {
+        self.embed_recipe
+    }
+
+    /// Converts output to the ANSI color palette
     pub fn set_ansi(&mut self, value: bool) {
-        self.0.options.ansi = value;
+        self.mosh.options.ansi = value;
     }
 
-    pub fn set_min_rate(&mut self, value: u16) {
-        self.0.options.min_rate = value;
+    /// Sets the minimum amount of chunks to process
+    ///
+    /// # Errors
+    ///
+    /// It fails if the value is outside [`Limits::min_rate`] or above the maximum rate.
+    pub fn set_min_rate(&mut self, value: u16) -> Result<(), Error> {
+        self.update_options(|options| options.min_rate = value)
     }
 
-    pub fn set_max_rate(&mut self, value: u16) {
-        self.0.options.max_rate = value;
+    /// Sets the maximum amount of chunks to process
+    ///
+    /// # Errors
+    ///
+    /// It fails if the value is outside [`Limits::max_rate`] or below the minimum rate.
+    pub fn set_max_rate(&mut self, value: u16) -> Result<(), Error> {
+        self.update_options(|options| options.max_rate = value)
     }
 
-    pub fn set_pixelation(&mut self, value: u8) {
-        self.0.options.pixelation = value;
+    /// Sets the pixelation intensity, `1` turns it off
+    ///
+    /// # Errors
+    ///
+    /// It fails if the value is outside [`Limits::pixelation`].
+    pub fn set_pixelation(&mut self, value: u8) -> Result<(), Error> {
+        self.update_options(|options| options.pixelation = value)
     }
 
-    pub fn set_line_shift(&mut self, value: f64) {
-        self.0.options.line_shift = value;
+    /// Sets the chance of line shift in `0..=1`
+    ///
+    /// # Errors
+    ///
+    /// It fails if the value is outside [`Limits::line_shift`].
+    pub fn set_line_shift(&mut self, value: f64) -> Result<(), Error> {
+        self.update_options(|options| options.line_shift = value)
     }
 
-    pub fn set_reverse(&mut self, value: f64) {
-        self.0.options.reverse = value;
+    /// Sets the chance of reverse in `0..=1`
+    ///
+    /// # Errors
+    ///
+    /// It fails if the value is outside [`Limits::reverse`].
+    pub fn set_reverse(&mut self, value: f64) -> Result<(), Error> {
+        self.update_options(|options| options.reverse = value)
     }
 
-    pub fn set_flip(&mut self, value: f64) {
-        self.0.options.flip = value;
+    /// Sets the chance of flip in `0..=1`
+    ///
+    /// # Errors
+    ///
+    /// It fails if the value is outside [`Limits::flip`].
+    pub fn set_flip(&mut self, value: f64) -> Result<(), Error> {
+        self.update_options(|options| options.flip = value)
+    }
+
+    /// Sets the chance of channel swap in `0..=1`
+    ///
+    /// # Errors
+    ///
+    /// It fails if the value is outside [`Limits::channel_swap`].
+    pub fn set_channel_swap(&mut self, value: f64) -> Result<(), Error> {
+        self.update_options(|options| options.channel_swap = value)
+    }
+
+    /// Sets the chance of channel shift in `0..=1`
+    ///
+    /// # Errors
+    ///
+    /// It fails if the value is outside [`Limits::channel_shift`].
+    pub fn set_channel_shift(&mut self, value: f64) -> Result<(), Error> {
+        self.update_options(|options| options.channel_shift = value)
     }
 
-    pub fn set_channel_swap(&mut self, value: f64) {
-        self.0.options.channel_swap = value;
+    /// Stores settings in the output image metadata
+    pub fn set_embed_recipe(&mut self, value: bool) {
+        self.embed_recipe = value;
+    }
+
+    /// Returns `true` if the ancillary chunk is copied to the output image
+    ///
+    /// Supported chunks: `gAMA`, `cHRM`, `sRGB`, `iCCP`, `pHYs`, `eXIf`
+    /// and `text` (all text chunks).
+    ///
+    /// # Errors
+    ///
+    /// It fails if the chunk is not supported.
+    pub fn keep_metadata(&self, chunk: &str) -> Result<bool, Error> {
+        Ok(self.metadata_filter.allows(Chunk::from_name(chunk)?))
+    }
+
+    /// Allows or strips an ancillary chunk in the output image
+    ///
+    /// # Errors
+    ///
+    /// It fails if the chunk is not supported.
+    pub fn set_keep_metadata(&mut self, chunk: &str, value: bool) -> Result<(), Error> {
+        self.metadata_filter.set(Chunk::from_name(chunk)?, value);
+
+        Ok(())
     }
 
-    pub fn set_channel_shift(&mut self, value: f64) {
-        self.0.options.channel_shift = value;
+    /// Returns the output format name: `png`, `jpeg`, `webp` or `qoi`
+    pub fn output_format(&self) -> String {
+        self.output.format().name().to_lowercase()
     }
 
+    /// Returns the MIME type of the output format
+    pub fn output_mime_type(&self) -> String {
+        self.output.mime_type().to_string()
+    }
+
+    /// Selects lossless PNG output
+    ///
+    /// Compression levels: `none`, `fastest`, `fast`, `balanced`, `high`.
+    /// Filters: `none`, `sub`, `up`, `avg`, `paeth`, `adaptive`.
+    ///
+    /// # Errors
+    ///
+    /// It fails if the compression level or filter is unknown.
+    pub fn set_output_png(
+        &mut self,
+        compression: &str,
+        filter: Option<String>,
+    ) -> Result<(), Error> {
+        self.output = OutputFormat::Png {
+            compression: encode::compression_from_name(compression)?,
+            filter: filter
+                .as_deref()
+                .map(encode::filter_from_name)
+                .transpose()?,
+        };
+
+        Ok(())
+    }
+
+    /// Selects lossy JPEG output with quality in `1..=100`
+    ///
+    /// # Errors
+    ///
+    /// It fails if the `jpeg` feature is not enabled or the quality is
+    /// outside [`Limits::quality`].
+    pub fn set_output_jpeg(&mut self, quality: Option<u8>) -> Result<(), Error> {
+        self.output = OutputFormat::Jpeg {
+            quality: quality.unwrap_or(encode::JPEG_QUALITY),
+        }
+        .check()?;
+
+        Ok(())
+    }
+
+    /// Selects WebP output, lossy with quality in `1..=100` or lossless
+    /// without
+    ///
+    /// # Errors
+    ///
+    /// It fails if the `webp` feature is not enabled. Lossy output also
+    /// needs the `webp-lossy` feature, which is not available on WebAssembly
+    /// targets, and a quality inside [`Limits::quality`].
+    pub fn set_output_webp(&mut self, quality: Option<u8>) -> Result<(), Error> {
+        self.output = OutputFormat::Webp { quality }.check()?;
+
+        Ok(())
+    }
+
+    /// Selects QOI output
+    ///
+    /// # Errors
+    ///
+    /// It fails if the `qoi` feature is not enabled.
+    pub fn set_output_qoi(&mut self) -> Result<(), Error> {
+        self.output = OutputFormat::Qoi.check()?;
+
+        Ok(())
+    }
+
+    /// Generates a new random seed
     pub fn new_seed(&mut self) {
-        self.0.options.new_seed();
+        self.mosh.options.new_seed();
+    }
+
+    /// Exports current settings as JSON
+    pub fn options_json(&self) -> String {
+        self.options().to_json()
+    }
+
+    /// Restores settings from JSON
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the JSON is malformed, uses an unknown schema version
+    /// or options are out of range.
+    pub fn set_options_json(&mut self, json: &str) -> Result<(), Error> {
+        self.set_options(&Options::from_json(json)?)
+    }
+
+    /// Creates a new instance with settings from JSON
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the JSON is malformed, uses an unknown schema version
+    /// or options are out of range.
+    pub fn from_options_json(json: &str) -> Result<Core, Error> {
+        let mut core = Self::default();
+        core.set_options_json(json)?;
+
+        Ok(core)
+    }
+
+    /// Creates a new instance with settings embedded in a processed image
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the image is not a valid PNG or has no embedded recipe.
+    pub fn recipe_from_png(image: &[u8]) -> Result<Core, Error> {
+        let mut core = Self::default();
+        core.set_recipe(&Recipe::from_png(image)?)?;
+
+        Ok(core)
     }
 
     /// Processes provided image data
     ///
+    /// PNG input of any bit depth is always supported, JPEG, WebP, GIF,
+    /// BMP and QOI require the matching cargo feature.
+    ///
+    /// Animated APNG and GIF input is processed frame by frame and encoded
+    /// in its own format, keeping frame timing. Frame seeds are selected
+    /// with [`Core::set_frame_seed_mode`] and [`Core::set_seed_schedule`].
+    ///
     /// # Errors
-    /// TODO
-    #[wasm_bindgen]
-    pub fn pixelmosh(&mut self, image: &[u8]) -> Result<Vec<u8>, JsValue> {
-        let mut output: Vec<u8> = Vec::new();
+    ///
+    /// * [`CorruptInput`]: image is truncated, invalid, or its format is not enabled.
+    /// * [`ImageTooLarge`]: image exceeds decoder limits.
+    /// * [`Encoder`]: output image could not be written.
+    /// * [`InvalidOptions`]: output format is not enabled.
+    ///
+    /// JavaScript callers receive a [`CoreError`] instead.
+    ///
+    /// [`CorruptInput`]: Error::CorruptInput
+    /// [`ImageTooLarge`]: Error::ImageTooLarge
+    /// [`Encoder`]: Error::Encoder
+    /// [`InvalidOptions`]: Error::InvalidOptions
+    pub fn pixelmosh(&mut self, image: &[u8]) -> Result<Vec<u8>, Error> {
+        self.pixelmosh_with_progress(image, &mut Progress::default())
+    }
 
-        self.0
-            .read_image(image)
-            .map_err(|error| JsValue::from(error.to_string()))?;
+    /// Decodes an image and keeps it for [`Core::remosh`]
+    ///
+    /// # Errors
+    ///
+    /// It fails under the same decoding conditions as [`Core::pixelmosh`].
+    pub fn load(&mut self, image: &[u8]) -> Result<(), Error> {
+        self.load_with_progress(image, &mut Progress::default())
+    }
 
-        self.0
-            .mosh()
-            .map_err(|error| JsValue::from(error.to_string()))?;
+    /// Processes the loaded image again with current settings
+    ///
+    /// # Errors
+    ///
+    /// It fails if no image is loaded, or under the same conditions as
+    /// [`Core::pixelmosh`].
+    pub fn remosh(&mut self) -> Result<Vec<u8>, Error> {
+        self.remosh_with_progress(&mut Progress::default())
+    }
+}
 
-        {
-            let mut encoder = png::Encoder::new(&mut output, self.0.data.width, self.0.data.height);
+#[cfg(feature = "js")]
+#[wasm_bindgen]
+impl Core {
+    /// Exports current settings as a plain object
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the object can not be created.
+    #[wasm_bindgen(js_name = options, unchecked_return_type = "Options")]
+    pub fn options_js(&self) -> Result<JsValue, Error> {
+        serde_wasm_bindgen::to_value(&self.options())
+            .map_err(|error| Error::InvalidOptions(error.to_string()))
+    }
 
-            encoder.set_color(if self.0.options.ansi {
-                ColorType::Indexed
-            } else {
-                self.0.data.color_type
-            });
+    /// Restores settings from a plain object
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the object is malformed, uses an unknown schema version
+    /// or options are out of range.
+    #[wasm_bindgen(js_name = set_options)]
+    pub fn set_options_js(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "Options")] value: JsValue,
+    ) -> Result<(), Error> {
+        let options: Options = serde_wasm_bindgen::from_value(value)
+            .map_err(|error| Error::InvalidOptions(error.to_string()))?;
+
+        self.set_options(&options)
+    }
 
-            encoder.set_depth(if self.0.options.ansi {
-                BitDepth::Eight
-            } else {
-                self.0.data.bit_depth
-            });
+    /// Returns valid ranges of numeric options as a plain object
+    ///
+    /// Each option has `min`, `max` and a suggested `step`.
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the object can not be created.
+    #[wasm_bindgen(js_name = limits, unchecked_return_type = "Limits")]
+    pub fn limits_js() -> Result<JsValue, Error> {
+        serde_wasm_bindgen::to_value(&LIMITS)
+            .map_err(|error| Error::InvalidOptions(error.to_string()))
+    }
 
-            if self.0.options.ansi {
-                encoder.set_palette(generate_palette());
-            };
+    /// Creates a new instance with settings from a plain object
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the object is malformed, uses an unknown schema version
+    /// or options are out of range.
+    #[wasm_bindgen(js_name = from_options)]
+    pub fn from_options_js(
+        #[wasm_bindgen(unchecked_param_type = "Options")] value: JsValue,
+    ) -> Result<Core, Error> {
+        let mut core = Self::default();
+        core.set_options_js(value)?;
+
+        Ok(core)
+    }
+}
 
-            let mut writer = encoder
-                .write_header()
-                .map_err(|error| JsValue::from(error.to_string()))?;
+impl Core {
+    /// Processes provided image data and reports progress
+    ///
+    /// # Errors
+    ///
+    /// It fails under the same conditions as [`Core::pixelmosh`], or with
+    /// [`Cancelled`](Error::Cancelled) once the token is cancelled.
+    pub fn pixelmosh_with_progress(
+        &mut self,
+        image: &[u8],
+        progress: &mut Progress,
+    ) -> Result<Vec<u8>, Error> {
+        self.load_with_progress(image, progress)?;
+        self.remosh_with_progress(progress)
+    }
 
-            writer
-                .write_image_data(&self.0.data.buf)
-                .map_err(|error| JsValue::from(error.to_string()))?;
+    /// Processes the loaded image again and reports progress
+    ///
+    /// Chunk passes, pixelation, ANSI conversion, the mask and encoding of
+    /// every frame are separate steps. Cancellation is checked before each
+    /// step, a started step runs to the end.
+    ///
+    /// # Errors
+    ///
+    /// It fails under the same conditions as [`Core::remosh`], or with
+    /// [`Cancelled`](Error::Cancelled) once the token is cancelled.
+    pub fn remosh_with_progress(&mut self, progress: &mut Progress) -> Result<Vec<u8>, Error> {
+        match &self.source {
+            Some(Source::Still { data, metadata }) => {
+                self.mosh.data.clone_from(data);
+                self.metadata.clone_from(&**metadata);
+            }
+            Some(Source::Animated(image)) => {
+                let image = image.clone();
+                let output = self.mosh_animated(&image, progress)?;
+                progress.report(Stage::Encode, 100)?;
+
+                return Ok(output);
+            }
+            None => {
+                return Err(Error::InvalidOptions(String::from("No image is loaded")));
+            }
         }
 
+        self.mosh_buffer(progress, Progress::span(0, 1))?;
+        progress.report(Stage::Encode, 90)?;
+
+        let output = self.encode()?;
+        progress.report(Stage::Encode, 100)?;
+
         Ok(output)
     }
+
+    /// Decodes an image for [`Core::remosh`] and reports progress
+    pub(crate) fn load_with_progress(
+        &mut self,
+        image: &[u8],
+        progress: &mut Progress,
+    ) -> Result<(), Error> {
+        self.source = None;
+        progress.report(Stage::Decode, 0)?;
+
+        let source = if decode::is_animated(image) {
+            Source::Animated(image.to_vec())
+        } else {
+            self.read(image)?;
+            self.mosh.data.buf.clear();
+
+            Source::Still {
+                data: self.mosh.data.clone(),
+                metadata: Box::new(self.metadata.clone()),
+            }
+        };
+
+        self.source = Some(source);
+
+        Ok(())
+    }
+
+    /// Decodes an image into the processing buffer
+    fn read(&mut self, image: &[u8]) -> Result<(), Error> {
+        self.metadata = match decode::read_image(&mut self.mosh, image)? {
+            Format::Png => Metadata::read(image)?,
+            _ => Metadata::default(),
+        };
+
+        Ok(())
+    }
+
+    /// Returns a snapshot of current settings
+    #[must_use]
+    pub fn options(&self) -> Options {
+        Options::from(&self.mosh.options)
+    }
+
+    /// Replaces current settings
+    ///
+    /// # Errors
+    ///
+    /// It fails if the options do not pass [`Options::validate`], current
+    /// settings are kept in that case.
+    pub fn set_options(&mut self, options: &Options) -> Result<(), Error> {
+        options.validate()?;
+        options.apply(&mut self.mosh.options);
+
+        Ok(())
+    }
+
+    /// Valid ranges of numeric options
+    #[must_use]
+    pub const fn limits() -> Limits {
+        LIMITS
+    }
+
+    /// Changes a copy of current settings and applies it if it is valid
+    fn update_options(&mut self, change: impl FnOnce(&mut Options)) -> Result<(), Error> {
+        let mut options = self.options();
+        change(&mut options);
+
+        self.set_options(&options)
+    }
+
+    /// Processes the buffer and applies the mask
+    ///
+    /// Chunk passes, pixelation, ANSI conversion and the mask are separate
+    /// steps reported within `span`, cancellation is checked before each.
+    /// Equal rates process exactly that many chunks.
+    pub(crate) fn mosh_buffer(
+        &mut self,
+        progress: &mut Progress,
+        span: Range<u8>,
+    ) -> Result<(), Error> {
+        let MoshOptions {
+            pixelation, ansi, ..
+        } = self.mosh.options;
+        let steps = 2 + u32::from(pixelation > 1) + u32::from(ansi);
+        let mut step = 0;
+        let mut report = |progress: &mut Progress| {
+            let percent = u32::from(span.start) + u32::from(span.end - span.start) * step / steps;
+            step += 1;
+
+            progress.report(Stage::Mosh, u8::try_from(percent).unwrap_or(span.end))
+        };
+
+        report(progress)?;
+        self.mosh_with(|options| {
+            // libmosh samples the chunk count from `min_rate..max_rate`
+            if options.min_rate == options.max_rate {
+                options.max_rate = options.max_rate.saturating_add(1);
+            }
+
+            options.pixelation = 1;
+            options.ansi = false;
+        })?;
+
+        if pixelation > 1 {
+            report(progress)?;
+
+            // Without chunks libmosh only pixelates, the moshed buffer
+            // stands in for the original meanwhile
+            let data = &mut self.mosh.data;
+            let original = std::mem::replace(&mut data.image, std::mem::take(&mut data.buf));
+            let result = self.mosh_with(|options| {
+                options.min_rate = 0;
+                options.max_rate = 1;
+                options.ansi = false;
+            });
+            self.mosh.data.image = original;
+            result?;
+        }
+
+        if ansi {
+            report(progress)?;
+            self.mosh
+                .data
+                .generate_ansi_data()
+                .map_err(Error::moshing)?;
+        }
+
+        report(progress)?;
+        self.composite()
+    }
+
+    /// Runs libmosh with changed options, current options are kept
+    fn mosh_with(&mut self, change: impl FnOnce(&mut MoshOptions)) -> Result<(), Error> {
+        let options = self.mosh.options.clone();
+        change(&mut self.mosh.options);
+
+        let result = self.mosh.mosh().map_err(Error::moshing);
+        self.mosh.options = options;
+
+        result
+    }
+
+    #[must_use]
+    pub fn metadata_filter(&self) -> MetadataFilter {
+        self.metadata_filter
+    }
+
+    /// Selects ancillary chunks copied to the output image
+    pub fn set_metadata_filter(&mut self, filter: MetadataFilter) {
+        self.metadata_filter = filter;
+    }
+
+    #[must_use]
+    pub fn output(&self) -> OutputFormat {
+        self.output
+    }
+
+    /// Selects the output encoder
+    ///
+    /// # Errors
+    ///
+    /// It fails if the format's cargo feature is not enabled or the quality
+    /// is outside [`Limits::quality`].
+    pub fn set_output(&mut self, output: OutputFormat) -> Result<(), Error> {
+        self.output = output.check()?;
+
+        Ok(())
+    }
+
+    /// Captures current options, mask, blend and output format
+    #[must_use]
+    pub fn recipe(&self) -> Recipe {
+        Recipe {
+            mask: self.mask.clone(),
+            blend: self.blend,
+            output: Some(self.output),
+            ..Recipe::new(self.options())
+        }
+    }
+
+    /// Applies settings of a recipe
+    ///
+    /// The mask and blend are replaced, the output format only if the
+    /// recipe has one.
+    ///
+    /// # Errors
+    ///
+    /// It fails if the recipe does not pass [`Recipe::validate`] or under
+    /// the same conditions as [`Core::set_output`]. Settings are kept
+    /// unchanged then.
+    pub fn set_recipe(&mut self, recipe: &Recipe) -> Result<(), Error> {
+        recipe.validate()?;
+        let output = recipe.output.map(OutputFormat::check).transpose()?;
+
+        recipe.options.apply(&mut self.mosh.options);
+        self.mask.clone_from(&recipe.mask);
+        self.blend = recipe.blend;
+        self.output = output.unwrap_or(self.output);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,164 @@
+use png::{Decoder, Info};
+
+use libmosh::err::MoshError;
+
+use std::io::Cursor;
+
+use crate::{error::Error, recipe::RECIPE_KEYWORD};
+
+/// Ancillary PNG chunks carried over from the source image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chunk {
+    Gamma,
+    Chromaticities,
+    Srgb,
+    IccProfile,
+    PixelDims,
+    Exif,
+    Text,
+}
+
+/// Selects ancillary chunks copied to the output image
+///
+/// All chunks are allowed by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MetadataFilter(u8);
+
+/// Ancillary chunks of the last decoded image
+#[derive(Clone, Debug, Default)]
+pub struct Metadata(Info<'static>);
+
+impl Chunk {
+    pub const ALL: [Self; 7] = [
+        Self::Gamma,
+        Self::Chromaticities,
+        Self::Srgb,
+        Self::IccProfile,
+        Self::PixelDims,
+        Self::Exif,
+        Self::Text,
+    ];
+
+    /// PNG chunk name, `text` covers `tEXt`, `zTXt` and `iTXt`
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Gamma => "gAMA",
+            Self::Chromaticities => "cHRM",
+            Self::Srgb => "sRGB",
+            Self::IccProfile => "iCCP",
+            Self::PixelDims => "pHYs",
+            Self::Exif => "eXIf",
+            Self::Text => "text",
+        }
+    }
+
+    /// Looks up a chunk by its name
+    ///
+    /// # Errors
+    ///
+    /// It fails if the chunk is not supported.
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        Self::ALL
+            .into_iter()
+            .find(|chunk| chunk.name() == name)
+            .ok_or_else(|| Error::InvalidOptions(format!("Unknown metadata chunk: {name}")))
+    }
+
+    const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl MetadataFilter {
+    /// Drops every ancillary chunk
+    #[must_use]
+    pub const fn none() -> Self {
+        Self(0)
+    }
+
+    #[must_use]
+    pub const fn allows(self, chunk: Chunk) -> bool {
+        self.0 & chunk.bit() != 0
+    }
+
+    pub fn set(&mut self, chunk: Chunk, allow: bool) {
+        if allow {
+            self.0 |= chunk.bit();
+        } else {
+            self.0 &= !chunk.bit();
+        }
+    }
+}
+
+impl Default for MetadataFilter {
+    fn default() -> Self {
+        Self(u8::MAX)
+    }
+}
+
+impl Metadata {
+    /// Reads ancillary chunks preceding the image data
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the image is not a valid PNG.
+    pub fn read(image: &[u8]) -> Result<Self, Error> {
+        let reader = Decoder::new(Cursor::new(image))
+            .read_info()
+            .map_err(|e| Error::decoding(MoshError::from(e)))?;
+
+        Ok(Self(reader.info().clone()))
+    }
+
+    /// Copies allowed chunks into the encoder info
+    pub(crate) fn apply(&self, info: &mut Info, filter: MetadataFilter) {
+        let source = &self.0;
+
+        if filter.allows(Chunk::Gamma) {
+            info.source_gamma = source.gama_chunk;
+        }
+
+        if filter.allows(Chunk::Chromaticities) {
+            info.source_chromaticities = source.chrm_chunk;
+        }
+
+        if filter.allows(Chunk::Srgb) {
+            info.srgb = source.srgb;
+        }
+
+        if filter.allows(Chunk::IccProfile) {
+            info.icc_profile.clone_from(&source.icc_profile);
+        }
+
+        if filter.allows(Chunk::PixelDims) {
+            info.pixel_dims = source.pixel_dims;
+        }
+
+        if filter.allows(Chunk::Exif) {
+            info.exif_metadata.clone_from(&source.exif_metadata);
+        }
+
+        if filter.allows(Chunk::Text) {
+            // Recipes are written again by the encoder when requested
+            info.uncompressed_latin1_text = source
+                .uncompressed_latin1_text
+                .iter()
+                .filter(|chunk| chunk.keyword != RECIPE_KEYWORD)
+                .cloned()
+                .collect();
+            info.compressed_latin1_text = source
+                .compressed_latin1_text
+                .iter()
+                .filter(|chunk| chunk.keyword != RECIPE_KEYWORD)
+                .cloned()
+                .collect();
+            info.utf8_text = source
+                .utf8_text
+                .iter()
+                .filter(|chunk| chunk.keyword != RECIPE_KEYWORD)
+                .cloned()
+                .collect();
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+
+use libmosh::MoshOptions;
+
+use std::fmt::Display;
+
+use crate::error::Error;
+
+/// Current options schema version
+pub const OPTIONS_VERSION: u32 = 1;
+
+/// Valid ranges of numeric options
+pub const LIMITS: Limits = Limits {
+    min_rate: Limit::new(1, 100, 1),
+    max_rate: Limit::new(1, 100, 1),
+    pixelation: Limit::new(1, 255, 1),
+    line_shift: Limit::new(0.0, 1.0, 0.1),
+    reverse: Limit::new(0.0, 1.0, 0.1),
+    flip: Limit::new(0.0, 1.0, 0.1),
+    channel_swap: Limit::new(0.0, 1.0, 0.1),
+    channel_shift: Limit::new(0.0, 1.0, 0.1),
+    opacity: Limit::new(0.0, 1.0, 0.1),
+    quality: Limit::new(1, 100, 1),
+};
+
+/// Inclusive range of a numeric option and its suggested step
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Limit<T> {
+    pub min: T,
+    pub max: T,
+    pub step: T,
+}
+
+/// Valid ranges of all numeric [`Options`], the blend opacity and the
+/// output quality
+///
+/// `min_rate` must not exceed `max_rate` in addition to the ranges.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Limits {
+    pub min_rate: Limit<u16>,
+    pub max_rate: Limit<u16>,
+    pub pixelation: Limit<u8>,
+    pub line_shift: Limit<f64>,
+    pub reverse: Limit<f64>,
+    pub flip: Limit<f64>,
+    pub channel_swap: Limit<f64>,
+    pub channel_shift: Limit<f64>,
+    pub opacity: Limit<f64>,
+    pub quality: Limit<u8>,
+}
+
+/// Serializable snapshot of all [`Core`](crate::Core) settings
+///
+/// The seed is stored as a decimal string, so it survives a round trip
+/// through JavaScript numbers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Options {
+    pub version: u32,
+    pub min_rate: u16,
+    pub max_rate: u16,
+    pub pixelation: u8,
+    pub line_shift: f64,
+    pub reverse: f64,
+    pub flip: f64,
+    pub channel_swap: f64,
+    pub channel_shift: f64,
+    pub ansi: bool,
+    #[serde(with = "seed")]
+    pub seed: u64,
+}
+
+impl Options {
+    /// Parses options from JSON
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the JSON is malformed, uses an unknown schema version
+    /// or options are out of range.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let options: Self =
+            serde_json::from_str(json).map_err(|e| Error::InvalidOptions(e.to_string()))?;
+
+        options.validate()?;
+
+        Ok(options)
+    }
+
+    /// Serializes options to JSON
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Options are always serializable")
+    }
+
+    /// Linear interpolation between two option sets
+    ///
+    /// The ANSI mode and seed are kept from `self`.
+    #[must_use]
+    pub fn interpolate(&self, target: &Self, t: f64) -> Self {
+        let lerp = |a: f64, b: f64| (b - a).mul_add(t, a);
+
+        Self {
+            min_rate: lerp(f64::from(self.min_rate), f64::from(target.min_rate)).round() as u16,
+            max_rate: lerp(f64::from(self.max_rate), f64::from(target.max_rate)).round() as u16,
+            pixelation: lerp(f64::from(self.pixelation), f64::from(target.pixelation)).round()
+                as u8,
+            line_shift: lerp(self.line_shift, target.line_shift),
+            reverse: lerp(self.reverse, target.reverse),
+            flip: lerp(self.flip, target.flip),
+            channel_swap: lerp(self.channel_swap, target.channel_swap),
+            channel_shift: lerp(self.channel_shift, target.channel_shift),
+            ..self.clone()
+        }
+    }
+
+    /// Checks the schema version and option ranges
+    ///
+    /// # Errors
+    ///
+    /// It fails if the version is unknown, an option is outside [`LIMITS`]
+    /// or `min_rate` exceeds `max_rate`.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.version != OPTIONS_VERSION {
+            return Err(Error::InvalidOptions(format!(
+                "Unsupported options version: {}",
+                self.version
+            )));
+        }
+
+        LIMITS.min_rate.check("min_rate", self.min_rate)?;
+        LIMITS.max_rate.check("max_rate", self.max_rate)?;
+        LIMITS.pixelation.check("pixelation", self.pixelation)?;
+        LIMITS.line_shift.check("line_shift", self.line_shift)?;
+        LIMITS.reverse.check("reverse", self.reverse)?;
+        LIMITS.flip.check("flip", self.flip)?;
+        LIMITS
+            .channel_swap
+            .check("channel_swap", self.channel_swap)?;
+        LIMITS
+            .channel_shift
+            .check("channel_shift", self.channel_shift)?;
+
+        if self.min_rate > self.max_rate {
+            return Err(Error::InvalidOptions(format!(
+                "min_rate ({}) exceeds max_rate ({})",
+                self.min_rate, self.max_rate
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn apply(&self, options: &mut MoshOptions) {
+        options.min_rate = self.min_rate;
+        options.max_rate = self.max_rate;
+        options.pixelation = self.pixelation;
+        options.line_shift = self.line_shift;
+        options.reverse = self.reverse;
+        options.flip = self.flip;
+        options.channel_swap = self.channel_swap;
+        options.channel_shift = self.channel_shift;
+        options.ansi = self.ansi;
+        options.seed = self.seed;
+    }
+}
+
+impl<T: PartialOrd + Copy + Display> Limit<T> {
+    const fn new(min: T, max: T, step: T) -> Self {
+        Self { min, max, step }
+    }
+
+    /// Returns `true` if the value is in range, `NaN` never is
+    pub fn contains(&self, value: T) -> bool {
+        self.min <= value && value <= self.max
+    }
+
+    /// Restricts the value to the range, `NaN` becomes the minimum
+    #[must_use]
+    pub fn clamp(&self, value: T) -> T {
+        if value > self.max {
+            self.max
+        } else if value >= self.min {
+            value
+        } else {
+            self.min
+        }
+    }
+
+    pub(crate) fn check(&self, name: &str, value: T) -> Result<(), Error> {
+        if self.contains(value) {
+            Ok(())
+        } else {
+            Err(Error::InvalidOptions(format!(
+                "{name} must be in {}..={}, got {value}",
+                self.min, self.max
+            )))
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::from(&MoshOptions::default())
+    }
+}
+
+impl From<&MoshOptions> for Options {
+    fn from(options: &MoshOptions) -> Self {
+        Self {
+            version: OPTIONS_VERSION,
+            min_rate: options.min_rate,
+            max_rate: options.max_rate,
+            pixelation: options.pixelation,
+            line_shift: options.line_shift,
+            reverse: options.reverse,
+            flip: options.flip,
+            channel_swap: options.channel_swap,
+            channel_shift: options.channel_shift,
+            ansi: options.ansi,
+            seed: options.seed,
+        }
+    }
+}
+
+mod seed {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&seed.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Seed {
+            Number(u64),
+            String(String),
+        }
+
+        match Seed::deserialize(deserializer)? {
+            Seed::Number(seed) => Ok(seed),
+            Seed::String(seed) => seed.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "js")]
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    Core,
+    composite::{Blend, Mask},
+    error::{Error, Stage},
+    options::Options,
+    progress::Progress,
+};
+
+/// Single pass of a [`Pipeline`]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pass {
+    pub options: Options,
+    /// Region moshed by this pass, the whole image if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask: Option<Mask>,
+    /// Blending of this pass over its input.
+    #[serde(default)]
+    pub blend: Blend,
+}
+
+/// Chain of mosh passes, each one starts from the output of the previous one
+///
+/// Every pass has its own options, seed, mask and blend. Only the last pass
+/// may convert output to the ANSI palette.
+#[cfg_attr(feature = "js", wasm_bindgen)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    passes: Vec<Pass>,
+}
+
+impl Pass {
+    #[must_use]
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            mask: None,
+            blend: Blend::default(),
+        }
+    }
+
+    /// Checks the options, mask and blend
+    ///
+    /// # Errors
+    ///
+    /// It fails if the options do not pass [`Options::validate`], the mask
+    /// image does not match its size or the opacity is out of range.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.options.validate()?;
+        self.blend.validate()?;
+
+        if let Some(mask) = &self.mask {
+            mask.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&Core> for Pass {
+    /// Captures current options, mask and blend
+    fn from(core: &Core) -> Self {
+        Self {
+            options: core.options(),
+            mask: core.mask.clone(),
+            blend: core.blend,
+        }
+    }
+}
+
+#[cfg_attr(feature = "js", wasm_bindgen)]
+impl Pipeline {
+    #[cfg_attr(feature = "js", wasm_bindgen(constructor))]
+    pub fn new() -> Pipeline {
+        Self::default()
+    }
+
+    /// Parses a pipeline from JSON
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the JSON is malformed or a pass does not pass
+    /// [`Pipeline::validate`].
+    pub fn from_json(json: &str) -> Result<Pipeline, Error> {
+        let pipeline: Self =
+            serde_json::from_str(json).map_err(|e| Error::InvalidOptions(e.to_string()))?;
+
+        pipeline.validate()?;
+
+        Ok(pipeline)
+    }
+
+    /// Serializes the pipeline to JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Pipelines are always serializable")
+    }
+
+    /// Returns the number of passes
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Appends a pass with current options, mask and blend of `core`
+    pub fn add_pass(&mut self, core: &Core) {
+        self.passes.push(Pass::from(core));
+    }
+
+    /// Removes the last pass, returns `false` if there was none
+    pub fn remove_last_pass(&mut self) -> bool {
+        self.passes.pop().is_some()
+    }
+
+    /// Removes all passes
+    pub fn clear(&mut self) {
+        self.passes.clear();
+    }
+}
+
+impl Pipeline {
+    #[must_use]
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    /// Appends a pass
+    pub fn push(&mut self, pass: Pass) {
+        self.passes.push(pass);
+    }
+
+    /// Checks every pass
+    ///
+    /// # Errors
+    ///
+    /// It fails if a pass does not pass [`Pass::validate`], or a pass other
+    /// than the last one uses ANSI output.
+    pub fn validate(&self) -> Result<(), Error> {
+        for (index, pass) in self.passes.iter().enumerate() {
+            pass.validate()
+                .map_err(|error| Error::InvalidOptions(format!("Pass {}: {error}", index + 1)))?;
+
+            if pass.options.ansi && index + 1 < self.passes.len() {
+                return Err(Error::InvalidOptions(format!(
+                    "Pass {}: only the last pass may use ANSI output",
+                    index + 1
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromIterator<Pass> for Pipeline {
+    fn from_iter<I: IntoIterator<Item = Pass>>(passes: I) -> Self {
+        Self {
+            passes: passes.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "js", wasm_bindgen)]
+impl Core {
+    /// Processes provided image data with every pass of the pipeline
+    ///
+    /// The image is decoded once and each pass moshes the output of the
+    /// previous one. Current settings only select the output format, they
+    /// are kept as they are. An embedded recipe holds the last pass settings.
+    ///
+    /// # Errors
+    ///
+    /// It fails if the pipeline is empty or does not pass
+    /// [`Pipeline::validate`], or under the same conditions as
+    /// [`Core::pixelmosh`].
+    pub fn run_pipeline(&mut self, image: &[u8], pipeline: &Pipeline) -> Result<Vec<u8>, Error> {
+        self.run_pipeline_with_progress(image, pipeline, &mut Progress::default())
+    }
+}
+
+impl Core {
+    /// Processes provided image data with every pass and reports progress
+    ///
+    /// # Errors
+    ///
+    /// It fails under the same conditions as [`Core::run_pipeline`], or
+    /// with [`Cancelled`](Error::Cancelled) once the token is cancelled.
+    pub fn run_pipeline_with_progress(
+        &mut self,
+        image: &[u8],
+        pipeline: &Pipeline,
+        progress: &mut Progress,
+    ) -> Result<Vec<u8>, Error> {
+        if pipeline.is_empty() {
+            return Err(Error::InvalidOptions(String::from(
+                "Pipeline needs at least one pass",
+            )));
+        }
+
+        pipeline.validate()?;
+
+        progress.report(Stage::Decode, 0)?;
+        self.read(image)?;
+
+        let options = self.options();
+        let mask = self.mask.take();
+        let blend = self.blend;
+
+        let output = self.run_passes(pipeline.passes(), progress);
+
+        options.apply(&mut self.mosh.options);
+        self.mask = mask;
+        self.blend = blend;
+
+        output
+    }
+
+    fn run_passes(&mut self, passes: &[Pass], progress: &mut Progress) -> Result<Vec<u8>, Error> {
+        let count = u32::try_from(passes.len()).unwrap_or(u32::MAX);
+
+        for (index, pass) in (0..count).zip(passes) {
+            // Later passes start from the previous output
+            if index > 0 {
+                self.mosh.data.image = std::mem::take(&mut self.mosh.data.buf);
+            }
+
+            pass.options.apply(&mut self.mosh.options);
+            self.mask.clone_from(&pass.mask);
+            self.blend = pass.blend;
+            self.mosh_buffer(progress, Progress::span(index, count))?;
+        }
+
+        progress.report(Stage::Encode, 90)?;
+        let output = self.encode()?;
+        progress.report(Stage::Encode, 100)?;
+
+        Ok(output)
+    }
+}
@@ -0,0 +1,135 @@
+#[cfg(feature = "js")]
+use js_sys::Function;
+#[cfg(feature = "js")]
+use wasm_bindgen::prelude::*;
+
+use std::{cell::Cell, ops::Range, rc::Rc};
+
+#[cfg(feature = "js")]
+use crate::Core;
+use crate::error::{Error, Stage};
+
+/// Stops a running job before its next step
+///
+/// Clones share the same state.
+#[cfg_attr(feature = "js", wasm_bindgen)]
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Rc<Cell<bool>>);
+
+/// Progress callback and cancellation token of a running job
+///
+/// It is passed to each job, the default one reports nowhere.
+pub struct Progress {
+    callback: Box<dyn FnMut(Stage, u8)>,
+    token: Option<CancelToken>,
+}
+
+#[cfg_attr(feature = "js", wasm_bindgen)]
+impl CancelToken {
+    #[cfg_attr(feature = "js", wasm_bindgen(constructor))]
+    pub fn new() -> CancelToken {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.set(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.get()
+    }
+
+    /// Allows the token to be used for another job
+    pub fn reset(&self) {
+        self.0.set(false);
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new(|_, _| {}, None)
+    }
+}
+
+impl Progress {
+    /// Reports to `callback`, stops once `token` is cancelled
+    pub fn new(callback: impl FnMut(Stage, u8) + 'static, token: Option<CancelToken>) -> Self {
+        Self {
+            callback: Box::new(callback),
+            token,
+        }
+    }
+
+    /// Reports a step, cancellation is checked before unfinished steps
+    pub(crate) fn report(&mut self, stage: Stage, percent: u8) -> Result<(), Error> {
+        if percent < 100 && self.token.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return Err(Error::Cancelled(stage));
+        }
+
+        (self.callback)(stage, percent);
+
+        Ok(())
+    }
+
+    /// Percentages of one frame or pass, moshing spans `10..90` in total
+    pub(crate) fn span(frame: u32, frames: u32) -> Range<u8> {
+        let percent = |frame: u32| {
+            let percent = 10 + u64::from(frame) * 80 / u64::from(frames.max(1));
+            u8::try_from(percent).unwrap_or(90)
+        };
+
+        percent(frame)..percent(frame.saturating_add(1))
+    }
+
+    #[cfg(feature = "js")]
+    fn js(callback: &Function, token: &CancelToken) -> Self {
+        let callback = callback.clone();
+
+        Self::new(
+            move |stage, percent| {
+                // Callback errors do not affect processing
+                let _ = callback.call2(&JsValue::NULL, &stage.as_str().into(), &percent.into());
+            },
+            Some(token.clone()),
+        )
+    }
+}
+
+#[cfg(feature = "js")]
+#[wasm_bindgen]
+impl Core {
+    /// Processes provided image data and reports progress
+    ///
+    /// `callback` receives the stage name (`decode`, `mosh` or `encode`)
+    /// and a percentage, `100` marks the finished job. Cancelling `token`
+    /// stops processing before the next step.
+    ///
+    /// # Errors
+    ///
+    /// It fails under the same conditions as [`Core::pixelmosh`], or with
+    /// [`Cancelled`](Error::Cancelled) once the token is cancelled.
+    #[wasm_bindgen(js_name = pixelmosh_with_progress)]
+    pub fn pixelmosh_with_progress_js(
+        &mut self,
+        image: &[u8],
+        #[wasm_bindgen(unchecked_param_type = "ProgressCallback")] callback: &Function,
+        token: &CancelToken,
+    ) -> Result<Vec<u8>, Error> {
+        self.pixelmosh_with_progress(image, &mut Progress::js(callback, token))
+    }
+
+    /// Processes the loaded image again and reports progress
+    ///
+    /// # Errors
+    ///
+    /// It fails under the same conditions as [`Core::remosh`], or with
+    /// [`Cancelled`](Error::Cancelled) once the token is cancelled.
+    #[wasm_bindgen(js_name = remosh_with_progress)]
+    pub fn remosh_with_progress_js(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "ProgressCallback")] callback: &Function,
+        token: &CancelToken,
+    ) -> Result<Vec<u8>, Error> {
+        self.remosh_with_progress(&mut Progress::js(callback, token))
+    }
+}
@@ -0,0 +1,155 @@
+use png::Decoder;
+use serde::{Deserialize, Serialize};
+
+use libmosh::err::MoshError;
+
+use std::io::Cursor;
+
+use crate::{
+    composite::{Blend, Mask},
+    encode::OutputFormat,
+    error::{Error, Stage},
+    options::Options,
+};
+
+/// Text chunk keyword used to store recipes
+pub const RECIPE_KEYWORD: &str = "webpixels:recipe";
+
+/// Current recipe schema version
+///
+/// Version 1 only holds options, version 2 adds the mask, blend and
+/// output format.
+pub const RECIPE_VERSION: u32 = 2;
+
+/// Settings embedded in processed images
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Recipe {
+    /// Schema version, recipes without one are version 1.
+    #[serde(default = "first_version")]
+    pub version: u32,
+    /// Crate name and version that produced the image.
+    pub generator: String,
+    pub options: Options,
+    /// Region moshed, the whole image if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask: Option<Mask>,
+    #[serde(default)]
+    pub blend: Blend,
+    /// Output format, current settings are kept if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputFormat>,
+}
+
+const fn first_version() -> u32 {
+    1
+}
+
+impl Recipe {
+    /// Creates a recipe with options only
+    #[must_use]
+    pub fn new(options: Options) -> Self {
+        Self {
+            version: RECIPE_VERSION,
+            generator: concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")).to_string(),
+            options,
+            mask: None,
+            blend: Blend::default(),
+            output: None,
+        }
+    }
+
+    /// Checks the schema version, options, mask and blend
+    ///
+    /// # Errors
+    ///
+    /// It fails if the version is unknown, the options do not pass
+    /// [`Options::validate`], the mask image does not match its size or
+    /// the opacity is out of range.
+    pub fn validate(&self) -> Result<(), Error> {
+        if !(1..=RECIPE_VERSION).contains(&self.version) {
+            return Err(Error::InvalidOptions(format!(
+                "Unsupported recipe version: {}",
+                self.version
+            )));
+        }
+
+        self.options.validate()?;
+        self.blend.validate()?;
+
+        if let Some(mask) = &self.mask {
+            mask.validate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Serializes the recipe to JSON
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Recipes are always serializable")
+    }
+
+    /// Parses a recipe from JSON
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the JSON is malformed or the recipe does not pass
+    /// [`Recipe::validate`].
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let recipe: Self =
+            serde_json::from_str(json).map_err(|e| Error::InvalidOptions(e.to_string()))?;
+
+        recipe.validate()?;
+
+        Ok(recipe)
+    }
+
+    /// Reads a recipe from PNG text chunks
+    ///
+    /// # Errors
+    ///
+    /// It may fail if the image is not a valid PNG or has no embedded recipe.
+    pub fn from_png(image: &[u8]) -> Result<Self, Error> {
+        let decoder = Decoder::new(Cursor::new(image));
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| Error::decoding(MoshError::from(e)))?;
+
+        // Text chunks may also follow the image data
+        if Self::find(reader.info()).is_none() {
+            let size = reader
+                .output_buffer_size()
+                .ok_or(Error::ImageTooLarge(Stage::Decode))?;
+            let mut buf = vec![0_u8; size];
+
+            reader
+                .next_frame(&mut buf)
+                .and_then(|_| reader.finish())
+                .map_err(|e| Error::decoding(MoshError::from(e)))?;
+        }
+
+        let json = Self::find(reader.info())
+            .ok_or_else(|| Error::InvalidOptions("Image has no embedded recipe".to_string()))?;
+
+        Self::from_json(&json)
+    }
+
+    fn find(info: &png::Info) -> Option<String> {
+        info.uncompressed_latin1_text
+            .iter()
+            .find(|chunk| chunk.keyword == RECIPE_KEYWORD)
+            .map(|chunk| chunk.text.clone())
+            .or_else(|| {
+                info.utf8_text
+                    .iter()
+                    .find(|chunk| chunk.keyword == RECIPE_KEYWORD)
+                    .and_then(|chunk| chunk.get_text().ok())
+            })
+            .or_else(|| {
+                info.compressed_latin1_text
+                    .iter()
+                    .find(|chunk| chunk.keyword == RECIPE_KEYWORD)
+                    .and_then(|chunk| chunk.get_text().ok())
+            })
+    }
+}
@@ -0,0 +1,105 @@
+#[cfg(feature = "js")]
+use wasm_bindgen::prelude::*;
+
+use crate::Core;
+
+/// Increment of the SplitMix64 generator
+const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Reproducible child seeds derived from a root seed
+///
+/// Seeds are produced by SplitMix64, so the same root always yields the
+/// same sequence on every platform and version.
+#[cfg_attr(feature = "js", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeedSequence {
+    root: u64,
+    index: u32,
+}
+
+/// Hashes a phrase into a seed
+///
+/// Uses 64-bit FNV-1a followed by a SplitMix64 mix, so the result is stable
+/// across platforms and versions.
+#[must_use]
+pub fn seed_from_phrase(phrase: &str) -> u64 {
+    let hash = phrase.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    });
+
+    mix(hash)
+}
+
+/// SplitMix64 output function
+const fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+    z ^ (z >> 31)
+}
+
+#[cfg_attr(feature = "js", wasm_bindgen)]
+impl SeedSequence {
+    #[cfg_attr(feature = "js", wasm_bindgen(constructor))]
+    pub fn new(root: u64) -> SeedSequence {
+        Self { root, index: 0 }
+    }
+
+    /// Creates a sequence rooted at the hash of a phrase
+    pub fn from_phrase(phrase: &str) -> SeedSequence {
+        Self::new(seed_from_phrase(phrase))
+    }
+
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    /// Returns the child seed at `index` without advancing the sequence
+    pub fn seed_at(&self, index: u32) -> u64 {
+        let state = GAMMA.wrapping_mul(u64::from(index) + 1);
+
+        mix(self.root.wrapping_add(state))
+    }
+
+    /// Returns the next child seed
+    pub fn next_seed(&mut self) -> u64 {
+        let seed = self.seed_at(self.index);
+        self.index = self.index.wrapping_add(1);
+
+        seed
+    }
+
+    /// Starts the sequence over
+    pub fn reset(&mut self) {
+        self.index = 0;
+    }
+}
+
+impl Iterator for SeedSequence {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        Some(self.next_seed())
+    }
+}
+
+#[cfg_attr(feature = "js", wasm_bindgen)]
+impl Core {
+    /// Sets the random seed, a `bigint` in JavaScript
+    pub fn set_seed(&mut self, seed: u64) {
+        self.mosh.options.seed = seed;
+    }
+
+    /// Sets the random seed to the hash of a phrase
+    pub fn set_seed_phrase(&mut self, phrase: &str) {
+        self.set_seed(seed_from_phrase(phrase));
+    }
+
+    /// Returns a sequence of child seeds rooted at the current seed
+    pub fn seed_sequence(&self) -> SeedSequence {
+        SeedSequence::new(self.seed())
+    }
+}
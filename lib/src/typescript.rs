@@ -0,0 +1,94 @@
+use wasm_bindgen::prelude::*;
+
+/// Types referenced by the generated `.d.ts` in place of `any`
+#[wasm_bindgen(typescript_custom_section)]
+const TYPES: &str = r#"
+/**
+ * Serializable settings of a `Core`, as used by `Core.options()`,
+ * `Core.set_options()` and `Core.from_options()`.
+ */
+export interface Options {
+  /** Options schema version. */
+  version: 1;
+  /** Minimum amount of chunks to process in `1..=100`. */
+  min_rate: number;
+  /** Maximum amount of chunks to process in `1..=100`, not below `min_rate`. */
+  max_rate: number;
+  /** Pixelation intensity in `1..=255`, `1` turns it off. */
+  pixelation: number;
+  /** Chance of line shift in `0..=1`. */
+  line_shift: number;
+  /** Chance of reverse in `0..=1`. */
+  reverse: number;
+  /** Chance of flip in `0..=1`. */
+  flip: number;
+  /** Chance of channel swap in `0..=1`. */
+  channel_swap: number;
+  /** Chance of channel shift in `0..=1`. */
+  channel_shift: number;
+  /** Convert to the ANSI color palette. */
+  ansi: boolean;
+  /** Random seed as a decimal string, numbers can not hold every `u64`. */
+  seed: string;
+}
+
+/** Inclusive range of a numeric option and its suggested step. */
+export interface Limit {
+  min: number;
+  max: number;
+  step: number;
+}
+
+/**
+ * Valid ranges returned by `Core.limits()`, `min_rate` must not exceed
+ * `max_rate` in addition.
+ */
+export interface Limits {
+  min_rate: Limit;
+  max_rate: Limit;
+  pixelation: Limit;
+  line_shift: Limit;
+  reverse: Limit;
+  flip: Limit;
+  channel_swap: Limit;
+  channel_shift: Limit;
+  opacity: Limit;
+  quality: Limit;
+}
+
+/** Error category of `CoreError.kind` and `WorkerError.kind`. */
+export type ErrorKind =
+  | "corrupt-input"
+  | "image-too-large"
+  | "encoder"
+  | "invalid-options"
+  | "cancelled";
+
+/** Processing stage of errors and progress reports. */
+export type Stage = "decode" | "mosh" | "encode" | "options";
+
+/** Seed mode for animated input. */
+export type FrameSeedMode = "fixed" | "per-frame" | "derived" | "schedule";
+
+/** Blend mode of the moshed image over the original. */
+export type BlendMode =
+  | "normal"
+  | "screen"
+  | "multiply"
+  | "difference"
+  | "lighten"
+  | "darken";
+
+/**
+ * Rejection value of `AsyncCore` promises. A worker failure rejects with
+ * an `Error` instead.
+ */
+export interface WorkerError {
+  kind: ErrorKind;
+  stage: Stage;
+  message: string;
+}
+
+/** Receives the stage and percentage of a running job, `100` marks the finished job. */
+export type ProgressCallback = (stage: Stage, percent: number) => void;
+"#;
@@ -0,0 +1,442 @@
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, prelude::*};
+use web_sys::{
+    DedicatedWorkerGlobalScope, ErrorEvent, MessageEvent, Worker, WorkerOptions, WorkerType,
+};
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use crate::{
+    Core,
+    encode::{self, OutputFormat},
+    error::{Error, Stage},
+    progress::Progress,
+};
+
+type Pending = Rc<RefCell<HashMap<u32, (Function, Function)>>>;
+
+/// Processes images in a dedicated worker
+///
+/// The worker script must call [`start_worker`]. Options, the recipe
+/// flag and the output format are copied from a [`Core`] on every call,
+/// other settings use their defaults.
+///
+/// Promises reject with a `WorkerError` object carrying `kind`, `stage`
+/// and `message`, or with an `Error` if the worker itself fails.
+#[wasm_bindgen]
+pub struct AsyncCore {
+    script_url: String,
+    module: bool,
+    worker: RefCell<Worker>,
+    pending: Pending,
+    progress: Rc<RefCell<Option<Function>>>,
+    /// Last loaded image, sent again after [`AsyncCore::cancel`].
+    loaded: RefCell<Option<Vec<u8>>>,
+    /// Set by [`AsyncCore::terminate`], later calls reject at once.
+    terminated: Cell<bool>,
+    next_id: Cell<u32>,
+    on_message: Closure<dyn FnMut(MessageEvent)>,
+    on_error: Closure<dyn FnMut(ErrorEvent)>,
+}
+
+#[wasm_bindgen]
+impl AsyncCore {
+    /// Starts a classic worker script
+    ///
+    /// # Errors
+    ///
+    /// It fails if the worker can not be created.
+    #[wasm_bindgen(constructor)]
+    pub fn new(script_url: &str) -> Result<AsyncCore, Error> {
+        Self::spawn(script_url, false)
+    }
+
+    /// Starts an ES module worker script
+    ///
+    /// # Errors
+    ///
+    /// It fails if the worker can not be created.
+    pub fn module(script_url: &str) -> Result<AsyncCore, Error> {
+        Self::spawn(script_url, true)
+    }
+
+    /// Reports progress of every job to `callback`
+    ///
+    /// It receives the stage name and a percentage, as with
+    /// [`Core::pixelmosh_with_progress`].
+    pub fn set_progress(
+        &self,
+        #[wasm_bindgen(unchecked_optional_param_type = "ProgressCallback")] callback: Option<
+            Function,
+        >,
+    ) {
+        *self.progress.borrow_mut() = callback;
+    }
+
+    /// Decodes an image in the worker and keeps it for [`AsyncCore::remosh`]
+    #[wasm_bindgen(unchecked_return_type = "Promise<void>")]
+    pub fn load(&self, image: &[u8]) -> Promise {
+        *self.loaded.borrow_mut() = Some(image.to_vec());
+
+        self.send("load", None, Some(image))
+    }
+
+    /// Processes the loaded image again, resolves with the output image
+    #[wasm_bindgen(unchecked_return_type = "Promise<Uint8Array>")]
+    pub fn remosh(&self, core: &Core) -> Promise {
+        self.send("remosh", Some(core), None)
+    }
+
+    /// Processes provided image data, resolves with the output image
+    #[wasm_bindgen(unchecked_return_type = "Promise<Uint8Array>")]
+    pub fn pixelmosh(&self, core: &Core, image: &[u8]) -> Promise {
+        self.send("pixelmosh", Some(core), Some(image))
+    }
+
+    /// Aborts running jobs by restarting the worker
+    ///
+    /// Pending promises reject with a `cancelled` error and the last
+    /// loaded image is loaded again. A terminated worker is not restarted.
+    ///
+    /// # Errors
+    ///
+    /// It fails if the new worker can not be created.
+    pub fn cancel(&self) -> Result<(), Error> {
+        if self.terminated.get() {
+            return Ok(());
+        }
+
+        let worker = Self::start(&self.script_url, self.module)?;
+        let previous = self.worker.replace(worker);
+
+        previous.set_onmessage(None);
+        previous.set_onerror(None);
+        previous.terminate();
+        self.attach();
+
+        reject_all(&self.pending, &error_object(&Error::Cancelled(Stage::Mosh)));
+
+        if let Some(image) = self.loaded.borrow().as_deref() {
+            // Failures are reported by the next job
+            let _ = self.send("load", None, Some(image));
+        }
+
+        Ok(())
+    }
+
+    /// Stops the worker and rejects pending promises
+    ///
+    /// Calls made afterwards reject with the same error.
+    pub fn terminate(&self) {
+        self.terminated.set(true);
+        self.worker.borrow().terminate();
+        reject_all(&self.pending, &terminated_error());
+    }
+}
+
+impl AsyncCore {
+    fn start(script_url: &str, module: bool) -> Result<Worker, Error> {
+        let worker = if module {
+            let options = WorkerOptions::new();
+            options.set_type(WorkerType::Module);
+
+            Worker::new_with_options(script_url, &options)
+        } else {
+            Worker::new(script_url)
+        };
+
+        worker.map_err(|error| Error::InvalidOptions(format!("Can not start worker: {error:?}")))
+    }
+
+    fn spawn(script_url: &str, module: bool) -> Result<Self, Error> {
+        let worker = Self::start(script_url, module)?;
+        let pending = Pending::default();
+        let progress: Rc<RefCell<Option<Function>>> = Rc::default();
+
+        let replies = Rc::clone(&pending);
+        let reports = Rc::clone(&progress);
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let reply = event.data();
+            let report = get(&reply, "progress");
+
+            if !report.is_undefined() {
+                if let Some(callback) = reports.borrow().as_ref() {
+                    let _ = callback.call2(
+                        &JsValue::NULL,
+                        &get(&report, "stage"),
+                        &get(&report, "percent"),
+                    );
+                }
+
+                return;
+            }
+
+            let id = get(&reply, "id").as_f64().unwrap_or(-1.0) as u32;
+            let Some((resolve, reject)) = replies.borrow_mut().remove(&id) else {
+                return;
+            };
+
+            let error = get(&reply, "error");
+            let _ = if error.is_undefined() {
+                resolve.call1(&JsValue::NULL, &get(&reply, "output"))
+            } else {
+                reject.call1(&JsValue::NULL, &error)
+            };
+        });
+
+        let failures = Rc::clone(&pending);
+        let on_error = Closure::<dyn FnMut(ErrorEvent)>::new(move |event: ErrorEvent| {
+            reject_all(&failures, &js_sys::Error::new(&event.message()));
+        });
+
+        let core = Self {
+            script_url: script_url.to_string(),
+            module,
+            worker: RefCell::new(worker),
+            pending,
+            progress,
+            loaded: RefCell::default(),
+            terminated: Cell::new(false),
+            next_id: Cell::new(0),
+            on_message,
+            on_error,
+        };
+
+        core.attach();
+
+        Ok(core)
+    }
+
+    fn attach(&self) {
+        let worker = self.worker.borrow();
+
+        worker.set_onmessage(Some(self.on_message.as_ref().unchecked_ref()));
+        worker.set_onerror(Some(self.on_error.as_ref().unchecked_ref()));
+    }
+
+    fn send(&self, action: &str, core: Option<&Core>, image: Option<&[u8]>) -> Promise {
+        if self.terminated.get() {
+            return Promise::reject(&terminated_error());
+        }
+
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+
+        let request = Object::new();
+        let transfer = Array::new();
+
+        set(&request, "id", &id.into());
+        set(&request, "action", &action.into());
+
+        if let Some(core) = core {
+            set(&request, "options", &core.options_json().into());
+            set(&request, "embed_recipe", &core.embed_recipe.into());
+            set(&request, "output", &output_object(core.output));
+        }
+
+        // Image data is copied once and moved to the worker
+        if let Some(image) = image {
+            let image = Uint8Array::from(image);
+            transfer.push(&image.buffer());
+            set(&request, "image", &image);
+        }
+
+        // Replies arrive on a later task, after the promise is registered
+        let posted = self
+            .worker
+            .borrow()
+            .post_message_with_transfer(&request, &transfer);
+
+        Promise::new(&mut |resolve, reject| match &posted {
+            Ok(()) => {
+                self.pending.borrow_mut().insert(id, (resolve, reject));
+            }
+            Err(error) => {
+                let _ = reject.call1(&JsValue::NULL, error);
+            }
+        })
+    }
+}
+
+impl Drop for AsyncCore {
+    fn drop(&mut self) {
+        let worker = self.worker.borrow();
+
+        worker.set_onmessage(None);
+        worker.set_onerror(None);
+        drop(worker);
+
+        self.terminate();
+    }
+}
+
+/// Serves [`AsyncCore`] requests in the current dedicated worker
+///
+/// Call it once from the worker script after the module is initialized.
+///
+/// # Errors
+///
+/// It fails outside of a dedicated worker.
+#[wasm_bindgen]
+pub fn start_worker() -> Result<(), Error> {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global()
+        .dyn_into()
+        .map_err(|_| Error::InvalidOptions(String::from("Not running in a dedicated worker")))?;
+
+    let responder = scope.clone();
+    let mut core = Core::default();
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+        let request = event.data();
+        let reply = Object::new();
+        let transfer = Array::new();
+
+        let id = get(&request, "id");
+        let reporter = responder.clone();
+        let mut progress = Progress::new(
+            move |stage, percent| {
+                let report = Object::new();
+                let message = Object::new();
+
+                set(&report, "stage", &stage.as_str().into());
+                set(&report, "percent", &percent.into());
+                set(&message, "id", &id);
+                set(&message, "progress", &report);
+
+                let _ = reporter.post_message(&message);
+            },
+            None,
+        );
+
+        set(&reply, "id", &get(&request, "id"));
+
+        match core.serve(&request, &mut progress) {
+            Ok(Some(output)) => {
+                let output = Uint8Array::from(output.as_slice());
+                transfer.push(&output.buffer());
+                set(&reply, "output", &output);
+            }
+            Ok(None) => {}
+            Err(error) => set(&reply, "error", &error_object(&error)),
+        }
+
+        // Posting only fails once the page is gone
+        let _ = responder.post_message_with_transfer(&reply, &transfer);
+    });
+
+    scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+
+    Ok(())
+}
+
+impl Core {
+    /// Runs a single worker request
+    fn serve(
+        &mut self,
+        request: &JsValue,
+        progress: &mut Progress,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(options) = get(request, "options").as_string() {
+            self.set_options_json(&options)?;
+            self.set_embed_recipe(get(request, "embed_recipe").is_truthy());
+            self.set_output_object(&get(request, "output"))?;
+        }
+
+        let image = || Uint8Array::new(&get(request, "image")).to_vec();
+
+        match get(request, "action").as_string().as_deref() {
+            Some("load") => self.load_with_progress(&image(), progress).map(|()| None),
+            Some("remosh") => self.remosh_with_progress(progress).map(Some),
+            Some("pixelmosh") => self.pixelmosh_with_progress(&image(), progress).map(Some),
+            action => Err(Error::InvalidOptions(format!(
+                "Unknown worker action: {}",
+                action.unwrap_or_default()
+            ))),
+        }
+    }
+
+    /// Applies output settings from [`output_object`]
+    fn set_output_object(&mut self, output: &JsValue) -> Result<(), Error> {
+        let field = |key| get(output, key).as_string();
+        let quality = || get(output, "quality").as_f64().map(|quality| quality as u8);
+
+        match field("format").as_deref() {
+            Some("jpeg") => self.set_output_jpeg(quality()),
+            Some("webp") => self.set_output_webp(quality()),
+            Some("qoi") => self.set_output_qoi(),
+            _ => self.set_output_png(
+                field("compression").as_deref().unwrap_or("balanced"),
+                field("filter"),
+            ),
+        }
+    }
+}
+
+/// Plain output settings, [`Core::set_output_object`] applies them in the worker
+fn output_object(output: OutputFormat) -> Object {
+    let object = Object::new();
+
+    set(
+        &object,
+        "format",
+        &output.format().name().to_lowercase().into(),
+    );
+
+    match output {
+        OutputFormat::Png {
+            compression,
+            filter,
+        } => {
+            set(
+                &object,
+                "compression",
+                &encode::compression_name(compression).into(),
+            );
+
+            if let Some(filter) = filter {
+                set(&object, "filter", &encode::filter_name(filter).into());
+            }
+        }
+        OutputFormat::Jpeg { quality }
+        | OutputFormat::Webp {
+            quality: Some(quality),
+        } => set(&object, "quality", &quality.into()),
+        OutputFormat::Webp { quality: None } | OutputFormat::Qoi => {}
+    }
+
+    object
+}
+
+/// Rejection of calls after [`AsyncCore::terminate`]
+fn terminated_error() -> JsValue {
+    js_sys::Error::new("Worker was terminated").into()
+}
+
+/// Plain error object, [`CoreError`](crate::CoreError) can not leave the worker
+fn error_object(error: &Error) -> Object {
+    let object = Object::new();
+
+    set(&object, "kind", &error.kind().as_str().into());
+    set(&object, "stage", &error.stage().as_str().into());
+    set(&object, "message", &error.to_string().into());
+
+    object
+}
+
+fn reject_all(pending: &Pending, error: &JsValue) {
+    for (_, (_, reject)) in pending.borrow_mut().drain() {
+        let _ = reject.call1(&JsValue::NULL, error);
+    }
+}
+
+fn get(target: &JsValue, key: &str) -> JsValue {
+    Reflect::get(target, &key.into()).unwrap_or_default()
+}
+
+fn set(target: &Object, key: &str, value: &JsValue) {
+    Reflect::set(target, &key.into(), value).expect("Plain objects accept properties");
+}
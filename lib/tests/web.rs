@@ -3,12 +3,89 @@
 extern crate wasm_bindgen_test;
 use wasm_bindgen_test::*;
 
-use webpixels::Core;
+use webpixels::{
+    Animation, BlendMode, CancelToken, Core, ErrorKind, Format, FrameSeeds, Mask, Options, Pass,
+    Pipeline, Progress, Recipe, Rect, SeedSequence, Stage, seed_from_phrase,
+};
+
+use std::{cell::RefCell, rc::Rc};
 
 pub mod images;
 
 wasm_bindgen_test_configure!(run_in_browser);
 
+fn tagged_image() -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut encoder = png::Encoder::new(&mut output, 16, 16);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_source_gamma(png::ScaledFloat::new(0.45455));
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: 11811,
+        yppu: 11811,
+        unit: png::Unit::Meter,
+    }));
+    encoder
+        .add_text_chunk("Author".to_string(), "webpixels".to_string())
+        .unwrap();
+
+    let data: Vec<u8> = (0..16 * 16 * 3).map(|i| (i % 251) as u8).collect();
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(&data)
+        .unwrap();
+
+    output
+}
+
+fn animated_image() -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut encoder = png::Encoder::new(&mut output, 64, 64);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_animated(3, 2).unwrap();
+
+    let mut writer = encoder.write_header().unwrap();
+    let full: Vec<u8> = (0..64 * 64 * 3).map(|i| (i * 7 % 251) as u8).collect();
+    let part: Vec<u8> = (0..32 * 32 * 3).map(|i| (i % 239) as u8).collect();
+
+    writer.write_image_data(&full).unwrap();
+    writer.set_frame_dimension(32, 32).unwrap();
+    writer.set_frame_position(16, 16).unwrap();
+    writer.set_frame_delay(1, 4).unwrap();
+    writer.set_dispose_op(png::DisposeOp::Background).unwrap();
+    writer.set_blend_op(png::BlendOp::Over).unwrap();
+    writer.write_image_data(&part).unwrap();
+    writer.reset_frame_position().unwrap();
+    writer.reset_frame_dimension().unwrap();
+    writer.write_image_data(&full).unwrap();
+    writer.finish().unwrap();
+
+    output
+}
+
+fn frame_controls(image: &[u8]) -> Vec<png::FrameControl> {
+    let mut reader = png::Decoder::new(std::io::Cursor::new(image))
+        .read_info()
+        .unwrap();
+    let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+    let frames = reader.info().animation_control.unwrap().num_frames;
+
+    (0..frames)
+        .map(|_| {
+            reader.next_frame(&mut buf).unwrap();
+            reader.info().frame_control.unwrap()
+        })
+        .collect()
+}
+
+fn read_info(image: &[u8]) -> png::Info<'static> {
+    png::Decoder::new(std::io::Cursor::new(image))
+        .read_info()
+        .unwrap()
+        .info()
+        .clone()
+}
+
 #[wasm_bindgen_test]
 fn valid_image() {
     let mut core = Core::default();
@@ -16,9 +93,1007 @@ fn valid_image() {
     assert!(!result.is_err());
 }
 
+#[cfg(not(feature = "jpeg"))]
 #[wasm_bindgen_test]
 fn invalid_image() {
     let mut core = Core::default();
     let result = core.pixelmosh(&images::INVALID_IMAGE.to_vec());
     assert!(result.is_err());
 }
+
+#[cfg(not(feature = "jpeg"))]
+#[wasm_bindgen_test]
+fn invalid_image_error() {
+    let mut core = Core::default();
+    let error = core.pixelmosh(&images::INVALID_IMAGE.to_vec()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::CorruptInput);
+    assert_eq!(error.stage(), Stage::Decode);
+}
+
+#[wasm_bindgen_test]
+fn truncated_image_error() {
+    let mut core = Core::default();
+    let error = core.pixelmosh(&images::VALID_IMAGE[..100]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::CorruptInput);
+}
+
+#[wasm_bindgen_test]
+fn options_json_round_trip() {
+    let mut core = Core::default();
+    core.set_min_rate(3).unwrap();
+    core.set_pixelation(4).unwrap();
+    core.set_flip(0.9).unwrap();
+    core.set_ansi(true);
+
+    let json = core.options_json();
+    let restored = Core::from_options_json(&json).unwrap();
+    assert_eq!(restored.options(), core.options());
+    assert_eq!(restored.options_json(), json);
+}
+
+#[wasm_bindgen_test]
+fn options_json_reproduces_output() {
+    let mut core = Core::default();
+    let first = core.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+
+    let mut restored = Core::from_options_json(&core.options_json()).unwrap();
+    let second = restored.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+    assert_eq!(first, second);
+}
+
+#[wasm_bindgen_test]
+fn options_json_unknown_version() {
+    let json = Core::default()
+        .options_json()
+        .replace("\"version\":1", "\"version\":99");
+    let error = Core::from_options_json(&json).err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::InvalidOptions);
+}
+
+#[wasm_bindgen_test]
+fn same_seed_same_output() {
+    let mut first = Core::default();
+    let mut second = Core::default();
+
+    for core in [&mut first, &mut second] {
+        core.set_pixelation(3).unwrap();
+        core.set_line_shift(0.8).unwrap();
+        core.set_seed(42);
+    }
+
+    let output = first.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+    assert_eq!(
+        second.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap(),
+        output
+    );
+}
+
+#[wasm_bindgen_test]
+fn seed_phrase() {
+    let mut core = Core::default();
+    core.set_seed_phrase("hello world");
+
+    // Phrase seeds must not change between versions
+    assert_eq!(core.seed(), 417_524_495_691_944_273);
+    assert_eq!(core.seed(), seed_from_phrase("hello world"));
+    assert_ne!(seed_from_phrase("Hello world"), core.seed());
+}
+
+#[wasm_bindgen_test]
+fn seed_sequence() {
+    let sequence = SeedSequence::new(0);
+    let seeds: Vec<u64> = sequence.take(4).collect();
+
+    // First SplitMix64 output for state 0
+    assert_eq!(seeds[0], 0xe220_a839_7b1d_cdaf);
+    assert_eq!(seeds[2], sequence.seed_at(2));
+    assert!(seeds.windows(2).all(|pair| pair[0] != pair[1]));
+    assert_eq!(SeedSequence::new(0).take(4).collect::<Vec<_>>(), seeds);
+    assert_eq!(FrameSeeds::Derived.seed(0, 3), seeds[3]);
+
+    let mut core = Core::default();
+    core.set_seed(0);
+    assert_eq!(core.seed_sequence(), sequence);
+}
+
+#[wasm_bindgen_test]
+fn option_limits() {
+    let mut core = Core::default();
+    let limits = Core::limits();
+
+    assert_eq!(limits.pixelation.min, 1);
+    assert_eq!(limits.flip.clamp(1.5), 1.0);
+    assert_eq!(limits.flip.clamp(f64::NAN), 0.0);
+
+    let options = core.options();
+    assert!(core.set_pixelation(0).is_err());
+    assert!(core.set_line_shift(-0.1).is_err());
+    assert!(core.set_reverse(f64::NAN).is_err());
+    assert!(core.set_min_rate(limits.min_rate.max + 1).is_err());
+    assert!(core.set_min_rate(core.max_rate() + 1).is_err());
+    assert!(core.set_max_rate(core.min_rate() - 1).is_err());
+    assert_eq!(core.options(), options);
+
+    let json = core.options_json().replace(
+        &format!("\"pixelation\":{}", core.pixelation()),
+        "\"pixelation\":0",
+    );
+    let error = core.set_options_json(&json).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidOptions);
+    assert_eq!(core.options(), options);
+}
+
+#[wasm_bindgen_test]
+fn equal_rates() {
+    let mut core = Core::default();
+    core.set_max_rate(5).unwrap();
+    core.set_min_rate(5).unwrap();
+
+    assert!(core.pixelmosh(&images::VALID_IMAGE.to_vec()).is_ok());
+    assert_eq!(core.max_rate(), 5);
+}
+
+#[wasm_bindgen_test]
+fn recipe_round_trip() {
+    let mut core = Core::default();
+    core.set_embed_recipe(true);
+    core.set_max_rate(12).unwrap();
+    let output = core.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+
+    let mut restored = Core::recipe_from_png(&output).unwrap();
+    restored.set_embed_recipe(true);
+    assert_eq!(restored.options(), core.options());
+    assert_eq!(
+        restored.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap(),
+        core.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap()
+    );
+}
+
+#[wasm_bindgen_test]
+fn recipe_settings() {
+    let mut core = Core::default();
+    core.set_embed_recipe(true);
+    core.add_mask_rect(0, 0, 32, 64);
+    core.set_blend_mode("screen").unwrap();
+    core.set_opacity(0.5).unwrap();
+    core.set_output_png("fast", Some("paeth".to_string()))
+        .unwrap();
+    let output = core.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+
+    let restored = Core::recipe_from_png(&output).unwrap();
+    assert_eq!(restored.recipe(), core.recipe());
+    assert_eq!(restored.mask(), core.mask());
+    assert_eq!(restored.blend(), core.blend());
+    assert_eq!(restored.output(), core.output());
+}
+
+#[wasm_bindgen_test]
+fn recipe_versions() {
+    let options = Options::default().to_json();
+    let first = format!(r#"{{"generator":"webpixels","options":{options}}}"#);
+    let recipe = Recipe::from_json(&first).unwrap();
+    assert_eq!(recipe.version, 1);
+    assert_eq!(recipe.mask, None);
+    assert_eq!(recipe.output, None);
+
+    let unknown = format!(r#"{{"version":99,"generator":"webpixels","options":{options}}}"#);
+    let error = Recipe::from_json(&unknown).err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::InvalidOptions);
+}
+
+#[wasm_bindgen_test]
+fn recipe_missing() {
+    let mut core = Core::default();
+    let output = core.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+    let error = Core::recipe_from_png(&output).err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::InvalidOptions);
+}
+
+#[wasm_bindgen_test]
+fn metadata_preserved() {
+    let mut core = Core::default();
+    let output = core.pixelmosh(&tagged_image()).unwrap();
+    let info = read_info(&output);
+
+    assert_eq!(info.gama_chunk, Some(png::ScaledFloat::new(0.45455)));
+    assert_eq!(info.pixel_dims.unwrap().xppu, 11811);
+    assert_eq!(info.uncompressed_latin1_text[0].keyword, "Author");
+}
+
+#[wasm_bindgen_test]
+fn metadata_stripped() {
+    let mut core = Core::default();
+    core.set_keep_metadata("text", false).unwrap();
+    core.set_keep_metadata("pHYs", false).unwrap();
+    let output = core.pixelmosh(&tagged_image()).unwrap();
+    let info = read_info(&output);
+
+    assert!(info.gama_chunk.is_some());
+    assert!(info.pixel_dims.is_none());
+    assert!(info.uncompressed_latin1_text.is_empty());
+    assert!(core.set_keep_metadata("IDAT", false).is_err());
+}
+
+#[wasm_bindgen_test]
+fn format_detection() {
+    assert_eq!(Format::detect(images::VALID_IMAGE), Some(Format::Png));
+    assert_eq!(Format::detect(images::INVALID_IMAGE), Some(Format::Jpeg));
+    assert_eq!(Format::detect(b"qoif\0\0\0\x01"), Some(Format::Qoi));
+    assert_eq!(Format::detect(b"plain text"), None);
+}
+
+#[cfg(feature = "jpeg")]
+#[wasm_bindgen_test]
+fn jpeg_image() {
+    let mut core = Core::default();
+    let result = core.pixelmosh(&images::INVALID_IMAGE.to_vec());
+    assert!(result.is_ok());
+}
+
+#[cfg(all(feature = "bmp", feature = "gif", feature = "qoi", feature = "webp"))]
+#[wasm_bindgen_test]
+fn raster_images() {
+    let source = image::RgbaImage::from_fn(24, 16, |x, y| {
+        image::Rgba([(x * 10) as u8, (y * 15) as u8, 128, 255])
+    });
+
+    for format in [
+        image::ImageFormat::Bmp,
+        image::ImageFormat::Gif,
+        image::ImageFormat::Qoi,
+        image::ImageFormat::WebP,
+    ] {
+        let mut input = std::io::Cursor::new(Vec::new());
+        source.write_to(&mut input, format).unwrap();
+
+        let mut core = Core::default();
+        let output = core.pixelmosh(input.get_ref()).unwrap();
+        assert_eq!(Format::detect(&output), Some(Format::Png));
+    }
+}
+
+#[wasm_bindgen_test]
+fn png_bit_depths() {
+    let encode = |color, depth, data: &[u8], palette: Option<Vec<u8>>| {
+        let mut output = Vec::new();
+        let mut encoder = png::Encoder::new(&mut output, 16, 16);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+
+        if let Some(palette) = palette {
+            encoder.set_palette(palette);
+        }
+
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+
+        output
+    };
+
+    let pattern = |size: usize| (0..size).map(|i| (i * 37 % 256) as u8).collect::<Vec<_>>();
+    let palette = (0..16 * 3).map(|i| (i * 5) as u8).collect();
+
+    for (input, color_type) in [
+        (
+            encode(
+                png::ColorType::Rgb,
+                png::BitDepth::Sixteen,
+                &pattern(16 * 16 * 6),
+                None,
+            ),
+            png::ColorType::Rgb,
+        ),
+        (
+            encode(
+                png::ColorType::Indexed,
+                png::BitDepth::Four,
+                &pattern(16 * 8),
+                Some(palette),
+            ),
+            png::ColorType::Rgb,
+        ),
+        (
+            encode(
+                png::ColorType::Grayscale,
+                png::BitDepth::One,
+                &pattern(16 * 2),
+                None,
+            ),
+            png::ColorType::Grayscale,
+        ),
+    ] {
+        let mut core = Core::default();
+        let info = read_info(&core.pixelmosh(&input).unwrap());
+        assert_eq!(info.bit_depth, png::BitDepth::Eight);
+        assert_eq!(info.color_type, color_type);
+    }
+}
+
+#[wasm_bindgen_test]
+fn png_output_settings() {
+    let mut core = Core::default();
+    core.set_output_png("high", Some("paeth".to_string()))
+        .unwrap();
+    let output = core.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+    assert_eq!(Format::detect(&output), Some(Format::Png));
+    assert!(core.set_output_png("maximum", None).is_err());
+    assert!(
+        core.set_output_png("fast", Some("random".to_string()))
+            .is_err()
+    );
+}
+
+#[cfg(not(feature = "jpeg"))]
+#[wasm_bindgen_test]
+fn jpeg_output_disabled() {
+    let mut core = Core::default();
+    let error = core.set_output_jpeg(Some(50)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidOptions);
+    assert_eq!(core.output_format(), "png");
+}
+
+#[cfg(feature = "jpeg")]
+#[wasm_bindgen_test]
+fn jpeg_quality_limits() {
+    let mut core = Core::default();
+    let quality = Core::limits().quality;
+
+    for value in [quality.min - 1, quality.max + 1] {
+        let error = core.set_output_jpeg(Some(value)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidOptions);
+        assert_eq!(core.output_format(), "png");
+    }
+
+    core.set_output_jpeg(Some(quality.max)).unwrap();
+    assert_eq!(core.output_format(), "jpeg");
+}
+
+#[cfg(all(feature = "jpeg", feature = "qoi", feature = "webp"))]
+#[wasm_bindgen_test]
+fn raster_output() {
+    let mut core = Core::default();
+
+    core.set_output_jpeg(Some(10)).unwrap();
+    let output = core.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+    assert_eq!(Format::detect(&output), Some(Format::Jpeg));
+    assert_eq!(core.output_mime_type(), "image/jpeg");
+
+    core.set_output_webp(None).unwrap();
+    let output = core.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+    assert_eq!(Format::detect(&output), Some(Format::Webp));
+
+    core.set_ansi(true);
+    core.set_output_qoi().unwrap();
+    let output = core.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+    assert_eq!(Format::detect(&output), Some(Format::Qoi));
+}
+
+#[cfg(all(feature = "webp-lossy", not(target_arch = "wasm32")))]
+#[wasm_bindgen_test]
+fn lossy_webp_output() {
+    let mut core = Core::default();
+    assert!(core.set_output_webp(Some(0)).is_err());
+
+    core.set_output_webp(Some(50)).unwrap();
+    let lossy = core.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+    assert_eq!(Format::detect(&lossy), Some(Format::Webp));
+    assert_eq!(&lossy[12..16], b"VP8 ");
+
+    core.set_output_webp(None).unwrap();
+    let lossless = core.pixelmosh(&images::VALID_IMAGE.to_vec()).unwrap();
+    assert_eq!(&lossless[12..16], b"VP8L");
+}
+
+#[cfg(all(
+    feature = "webp",
+    not(all(feature = "webp-lossy", not(target_arch = "wasm32")))
+))]
+#[wasm_bindgen_test]
+fn lossy_webp_unavailable() {
+    let mut core = Core::default();
+    let error = core.set_output_webp(Some(50)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidOptions);
+    core.set_output_webp(None).unwrap();
+}
+
+#[wasm_bindgen_test]
+fn load_remosh() {
+    let image = images::VALID_IMAGE.to_vec();
+    let mut core = Core::default();
+    assert!(core.remosh().is_err());
+
+    core.load(&image).unwrap();
+    let first = core.remosh().unwrap();
+    assert_eq!(core.remosh().unwrap(), first);
+    assert_eq!(core.pixelmosh(&image).unwrap(), first);
+
+    core.load(&animated_image()).unwrap();
+    assert_eq!(frame_controls(&core.remosh().unwrap()).len(), 3);
+}
+
+#[wasm_bindgen_test]
+fn progress_reports() {
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    let recorder = Rc::clone(&reports);
+    let mut progress = Progress::new(
+        move |stage, percent| recorder.borrow_mut().push((stage, percent)),
+        None,
+    );
+
+    let mut core = Core::default();
+    core.pixelmosh_with_progress(&animated_image(), &mut progress)
+        .unwrap();
+
+    // Chunk, pixelation and mask steps of three frames
+    let reports = reports.take();
+    assert_eq!(reports.first(), Some(&(Stage::Decode, 0)));
+    assert_eq!(reports.last(), Some(&(Stage::Encode, 100)));
+    assert_eq!(
+        reports
+            .iter()
+            .filter(|(stage, _)| *stage == Stage::Mosh)
+            .count(),
+        9
+    );
+    assert!(reports.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+}
+
+#[wasm_bindgen_test]
+fn progress_cancelled() {
+    let token = CancelToken::new();
+    let canceller = token.clone();
+    let mut progress = Progress::new(move |_, _| canceller.cancel(), Some(token.clone()));
+
+    let mut core = Core::default();
+    let error = core
+        .pixelmosh_with_progress(images::VALID_IMAGE, &mut progress)
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Cancelled);
+    assert_eq!(error.stage(), Stage::Mosh);
+    assert!(token.is_cancelled());
+
+    token.reset();
+    assert!(core.remosh().is_ok());
+}
+
+#[wasm_bindgen_test]
+fn mosh_steps_match_libmosh() {
+    for ansi in [false, true] {
+        let mut core = Core::default();
+        core.set_seed(42);
+        core.set_pixelation(4).unwrap();
+        core.set_ansi(ansi);
+        let output = core.pixelmosh(images::VALID_IMAGE).unwrap();
+
+        let mut reader = png::Decoder::new(std::io::Cursor::new(output))
+            .read_info()
+            .unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut buf).unwrap();
+
+        let options = core.options();
+        let mut mosh = libmosh::MoshCore::new();
+        mosh.options.min_rate = options.min_rate;
+        mosh.options.max_rate = options.max_rate;
+        mosh.options.pixelation = options.pixelation;
+        mosh.options.line_shift = options.line_shift;
+        mosh.options.reverse = options.reverse;
+        mosh.options.flip = options.flip;
+        mosh.options.channel_swap = options.channel_swap;
+        mosh.options.channel_shift = options.channel_shift;
+        mosh.options.ansi = options.ansi;
+        mosh.options.seed = options.seed;
+        mosh.read_image(images::VALID_IMAGE).unwrap();
+        mosh.mosh().unwrap();
+        assert_eq!(buf, mosh.data.buf);
+    }
+}
+
+#[wasm_bindgen_test]
+fn progress_cancelled_between_steps() {
+    let token = CancelToken::new();
+    let canceller = token.clone();
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    let recorder = Rc::clone(&reports);
+    let mut progress = Progress::new(
+        move |stage, percent| {
+            // Chunk passes are done once the next step is reported
+            if stage == Stage::Mosh && percent > 10 {
+                canceller.cancel();
+            }
+
+            recorder.borrow_mut().push((stage, percent));
+        },
+        Some(token),
+    );
+
+    let mut core = Core::default();
+    core.set_pixelation(4).unwrap();
+    core.set_ansi(true);
+
+    let error = core
+        .pixelmosh_with_progress(images::VALID_IMAGE, &mut progress)
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Cancelled);
+    assert_eq!(error.stage(), Stage::Mosh);
+
+    // Pixelation ran, ANSI conversion was not started
+    let reports = reports.take();
+    assert_eq!(reports.last(), Some(&(Stage::Mosh, 30)));
+}
+
+#[wasm_bindgen_test]
+fn rgba_in_place() {
+    let mut core = Core::default();
+    let source: Vec<u8> = (0..64 * 64 * 4).map(|i| (i * 7 % 251) as u8).collect();
+    let mut pixels = source.clone();
+
+    core.mosh_rgba(64, 64, &mut pixels).unwrap();
+    assert_eq!(pixels.len(), source.len());
+    assert_ne!(pixels, source);
+
+    let info = read_info(&core.export().unwrap());
+    assert_eq!((info.width, info.height), (64, 64));
+    assert_eq!(info.color_type, png::ColorType::Rgba);
+
+    core.set_ansi(true);
+    core.mosh_rgba(64, 64, &mut pixels).unwrap();
+    assert!(pixels.chunks_exact(4).all(|pixel| pixel[3] == u8::MAX));
+
+    let error = core.mosh_rgba(64, 32, &mut pixels).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::CorruptInput);
+}
+
+#[wasm_bindgen_test]
+fn rgba_empty() {
+    let mut core = Core::default();
+
+    for (width, height) in [(0, 0), (0, 64), (64, 0)] {
+        let error = core.mosh_rgba(width, height, &mut []).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::CorruptInput);
+    }
+}
+
+fn gray_png(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut encoder = png::Encoder::new(&mut output, width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(pixels).unwrap();
+    writer.finish().unwrap();
+
+    output
+}
+
+/// Moshes a 64x64 RGBA image with a fixed seed
+fn masked_mosh(core: &mut Core) -> (Vec<u8>, Vec<u8>) {
+    let source: Vec<u8> = (0..64 * 64 * 4).map(|i| (i * 7 % 251) as u8).collect();
+    let mut pixels = source.clone();
+
+    core.set_seed(7);
+    core.set_pixelation(4).unwrap();
+    core.mosh_rgba(64, 64, &mut pixels).unwrap();
+
+    (source, pixels)
+}
+
+#[wasm_bindgen_test]
+fn mask_rect() {
+    let mut core = Core::default();
+    core.add_mask_rect(0, 0, 32, 64);
+
+    let (source, pixels) = masked_mosh(&mut core);
+    let rows = source.chunks_exact(64 * 4).zip(pixels.chunks_exact(64 * 4));
+
+    for (source, pixels) in rows.clone() {
+        assert_eq!(pixels[32 * 4..], source[32 * 4..]);
+    }
+
+    assert!(
+        rows.clone()
+            .any(|(source, pixels)| pixels[..32 * 4] != source[..32 * 4])
+    );
+
+    core.set_mask_invert(true);
+    let (source, pixels) = masked_mosh(&mut core);
+    let rows = source.chunks_exact(64 * 4).zip(pixels.chunks_exact(64 * 4));
+
+    for (source, pixels) in rows {
+        assert_eq!(pixels[..32 * 4], source[..32 * 4]);
+    }
+}
+
+#[wasm_bindgen_test]
+fn mask_apng_frame_offset() {
+    let mut mask = Mask::default();
+    mask.rects.push(Rect::new(0, 0, 32, 64));
+
+    // The 32x32 frame at 16x16 only overlaps the mask with its left half
+    let coverage = mask.frame_coverage(64, 64, Rect::new(16, 16, 32, 32));
+    assert_eq!(coverage.len(), 32 * 32);
+    for row in coverage.chunks_exact(32) {
+        assert!(row[..16].iter().all(|&value| value == u8::MAX));
+        assert!(row[16..].iter().all(|&value| value == 0));
+    }
+
+    let mut core = Core::default();
+    core.set_seed(7);
+    core.set_pixelation(4).unwrap();
+    core.set_mask(Some(mask));
+    let output = core.pixelmosh(&animated_image()).unwrap();
+
+    let mut reader = png::Decoder::new(std::io::Cursor::new(output))
+        .read_info()
+        .unwrap();
+    let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+    reader.next_frame(&mut buf).unwrap();
+    let frame = reader.next_frame(&mut buf).unwrap();
+    assert_eq!((frame.width, frame.height), (32, 32));
+
+    let part: Vec<u8> = (0..32 * 32 * 3).map(|i| (i % 239) as u8).collect();
+    let rows = part
+        .chunks_exact(32 * 3)
+        .zip(buf[..frame.buffer_size()].chunks_exact(32 * 3));
+
+    for (source, pixels) in rows.clone() {
+        assert_eq!(pixels[16 * 3..], source[16 * 3..]);
+    }
+
+    assert!(
+        rows.clone()
+            .any(|(source, pixels)| pixels[..16 * 3] != source[..16 * 3])
+    );
+}
+
+#[wasm_bindgen_test]
+fn mask_png() {
+    let mut core = Core::default();
+    core.add_mask_rect(0, 0, 32, 64);
+    let (_, expected) = masked_mosh(&mut core);
+
+    // Scaled to the left half of the image
+    core.clear_mask();
+    core.set_mask_png(&gray_png(2, 1, &[255, 0])).unwrap();
+    assert_eq!(masked_mosh(&mut core).1, expected);
+
+    let error = core.set_mask_png(&[1, 2, 3]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::CorruptInput);
+}
+
+#[wasm_bindgen_test]
+fn mask_empty_image() {
+    let mask: Mask =
+        serde_json::from_str(r#"{"image":{"width":0,"height":0,"coverage":[]}}"#).unwrap();
+    assert!(mask.coverage(4, 4).iter().all(|&value| value == 0));
+
+    let mut core = Core::default();
+    core.set_mask(Some(mask));
+
+    let mut pixels = vec![0; 64 * 64 * 4];
+    let error = core.mosh_rgba(64, 64, &mut pixels).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidOptions);
+}
+
+#[wasm_bindgen_test]
+fn mask_feather() {
+    let mut mask = Mask::default();
+    mask.rects.push(Rect::new(0, 0, 16, 32));
+    mask.feather = 2;
+
+    let coverage = mask.coverage(32, 32);
+
+    assert_eq!(coverage.len(), 32 * 32);
+    assert_eq!(coverage[0], 255);
+    assert!((1..255).contains(&coverage[15]));
+    assert!((1..255).contains(&coverage[16]));
+    assert_eq!(coverage[31], 0);
+}
+
+#[wasm_bindgen_test]
+fn blend_opacity() {
+    let mut core = Core::default();
+    let (_, moshed) = masked_mosh(&mut core);
+
+    core.set_opacity(0.0).unwrap();
+    let (source, pixels) = masked_mosh(&mut core);
+    assert_eq!(pixels, source);
+
+    core.set_opacity(0.5).unwrap();
+    let (source, pixels) = masked_mosh(&mut core);
+    assert_ne!(pixels, source);
+    assert_ne!(pixels, moshed);
+
+    for ((&value, &bottom), &top) in pixels.iter().zip(&source).zip(&moshed) {
+        assert!((bottom.min(top)..=bottom.max(top)).contains(&value));
+    }
+
+    assert_eq!(
+        core.set_opacity(1.5).unwrap_err().kind(),
+        ErrorKind::InvalidOptions
+    );
+    assert_eq!(core.opacity(), 0.5);
+}
+
+#[wasm_bindgen_test]
+fn blend_modes() {
+    let mut core = Core::default();
+
+    // Transparency is not blended, only colors are compared
+    let colors = |pixels: &[u8]| -> Vec<u8> {
+        pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| pixel[..3].to_vec())
+            .collect()
+    };
+
+    core.set_blend_mode("lighten").unwrap();
+    assert_eq!(core.blend().mode, BlendMode::Lighten);
+    let (source, pixels) = masked_mosh(&mut core);
+    let (source, pixels) = (colors(&source), colors(&pixels));
+    assert!(
+        pixels
+            .iter()
+            .zip(&source)
+            .all(|(value, source)| value >= source)
+    );
+    assert_ne!(pixels, source);
+
+    core.set_blend_mode("darken").unwrap();
+    let (source, pixels) = masked_mosh(&mut core);
+    let (source, pixels) = (colors(&source), colors(&pixels));
+    assert!(
+        pixels
+            .iter()
+            .zip(&source)
+            .all(|(value, source)| value <= source)
+    );
+    assert_ne!(pixels, source);
+
+    core.set_blend_mode("difference").unwrap();
+    assert_eq!(core.blend_mode(), "difference");
+    assert!(core.set_blend_mode("overlay").is_err());
+    assert_eq!(core.blend_mode(), "difference");
+}
+
+#[wasm_bindgen_test]
+fn pipeline_passes() {
+    let image = tagged_image();
+    let mut core = Core::default();
+    core.set_seed(1);
+    core.set_line_shift(0.3).unwrap();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_pass(&core);
+
+    // A single pass matches a plain run
+    assert_eq!(
+        core.run_pipeline(&image, &pipeline).unwrap(),
+        core.pixelmosh(&image).unwrap()
+    );
+
+    let mut pass = Pass::new(Options {
+        seed: 2,
+        pixelation: 4,
+        ..Options::default()
+    });
+    pass.mask = Some(Mask::default());
+    pipeline.push(pass);
+    assert_eq!(pipeline.len(), 2);
+
+    let output = core.run_pipeline(&image, &pipeline).unwrap();
+    assert_ne!(output, core.pixelmosh(&image).unwrap());
+    assert_eq!(core.run_pipeline(&image, &pipeline).unwrap(), output);
+    assert_eq!(core.seed(), 1);
+
+    let copy = Pipeline::from_json(&pipeline.to_json()).unwrap();
+    assert_eq!(copy, pipeline);
+    assert_eq!(core.run_pipeline(&image, &copy).unwrap(), output);
+}
+
+#[wasm_bindgen_test]
+fn pipeline_validation() {
+    let image = images::VALID_IMAGE.to_vec();
+    let mut core = Core::default();
+    let mut pipeline = Pipeline::new();
+
+    assert!(core.run_pipeline(&image, &pipeline).is_err());
+
+    core.set_ansi(true);
+    pipeline.add_pass(&core);
+    assert!(core.run_pipeline(&image, &pipeline).is_ok());
+
+    pipeline.add_pass(&core);
+    assert_eq!(
+        core.run_pipeline(&image, &pipeline).unwrap_err().kind(),
+        ErrorKind::InvalidOptions
+    );
+
+    assert!(pipeline.remove_last_pass());
+    assert_eq!(pipeline.len(), 1);
+    assert!(Pipeline::from_json(r#"{"passes":[{"options":{"version":2}}]}"#).is_err());
+    assert!(
+        Pipeline::from_json(
+            r#"{"passes":[{"options":{"version":1,"min_rate":1,"max_rate":7,"pixelation":1,"line_shift":0,"reverse":0,"flip":0,"channel_swap":0,"channel_shift":0,"ansi":false,"seed":"1"},"mask":{"image":{"width":2,"height":2,"coverage":[0]}}}]}"#
+        )
+        .is_err()
+    );
+}
+
+#[wasm_bindgen_test]
+fn pipeline_empty_mask() {
+    let json = r#"{"passes":[{"options":{"version":1,"min_rate":1,"max_rate":7,"pixelation":1,"line_shift":0,"reverse":0,"flip":0,"channel_swap":0,"channel_shift":0,"ansi":false,"seed":"1"},"mask":{"image":{"width":0,"height":0,"coverage":[]}}}]}"#;
+    let error = Pipeline::from_json(json).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidOptions);
+
+    let mut pass = Pass::new(Options::default());
+    pass.mask =
+        Some(serde_json::from_str(r#"{"image":{"width":0,"height":1,"coverage":[]}}"#).unwrap());
+
+    let pipeline = Pipeline::from_iter([pass]);
+    let error = Core::default()
+        .run_pipeline(&tagged_image(), &pipeline)
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidOptions);
+}
+
+#[wasm_bindgen_test]
+fn apng_animation() {
+    let mut core = Core::default();
+    let mut animation = Animation::new(4);
+    animation.delay = 80;
+    animation.loops = 2;
+
+    let output = core
+        .animate(&images::VALID_IMAGE.to_vec(), &animation)
+        .unwrap();
+    let control = read_info(&output).animation_control.unwrap();
+    assert_eq!(control.num_frames, 4);
+    assert_eq!(control.num_plays, 2);
+    assert!(
+        core.animate(&images::VALID_IMAGE.to_vec(), &Animation::new(0))
+            .is_err()
+    );
+}
+
+#[wasm_bindgen_test]
+fn animation_interpolation() {
+    let start = Options::default();
+    let target = Options {
+        pixelation: 30,
+        flip: 1.0,
+        ..start.clone()
+    };
+
+    let mut animation = Animation::new(5);
+    animation.set_target(Some(target.clone()));
+
+    let first = animation.frame_options(&start, 0);
+    let last = animation.frame_options(&start, 4);
+    assert_eq!(first.pixelation, start.pixelation);
+    assert_eq!(last.pixelation, target.pixelation);
+    assert_eq!(last.flip, target.flip);
+    assert_eq!(last.seed, SeedSequence::new(start.seed).seed_at(4));
+
+    // Neighbouring start seeds do not share frame seeds
+    let next = Options {
+        seed: start.seed.wrapping_add(1),
+        ..start.clone()
+    };
+    assert_ne!(
+        animation.frame_options(&start, 1).seed,
+        animation.frame_options(&next, 0).seed
+    );
+}
+
+#[wasm_bindgen_test]
+fn apng_input() {
+    let image = animated_image();
+    let mut core = Core::default();
+
+    let output = core.pixelmosh(&image).unwrap();
+    let source = frame_controls(&image);
+    let frames = frame_controls(&output);
+    assert_eq!(frames.len(), 3);
+    assert_eq!(read_info(&output).animation_control.unwrap().num_plays, 2);
+
+    for (frame, source) in frames.iter().zip(&source) {
+        assert_eq!(
+            (frame.width, frame.height, frame.x_offset, frame.y_offset),
+            (
+                source.width,
+                source.height,
+                source.x_offset,
+                source.y_offset
+            )
+        );
+        assert_eq!(
+            (frame.delay_num, frame.delay_den),
+            (source.delay_num, source.delay_den)
+        );
+        assert_eq!(frame.dispose_op, source.dispose_op);
+        assert_eq!(frame.blend_op, source.blend_op);
+    }
+}
+
+#[wasm_bindgen_test]
+fn apng_input_seeds() {
+    let image = animated_image();
+    let mut core = Core::default();
+    let seed = core.seed();
+
+    let fixed = core.pixelmosh(&image).unwrap();
+    core.set_frame_seed_mode("per-frame").unwrap();
+    let per_frame = core.pixelmosh(&image).unwrap();
+    core.set_seed_schedule(vec![seed]);
+    let schedule = core.pixelmosh(&image).unwrap();
+
+    assert_eq!(core.frame_seed_mode(), "schedule");
+    assert_eq!(core.seed(), seed);
+    assert_eq!(schedule, fixed);
+    assert_ne!(per_frame, fixed);
+    assert_eq!(FrameSeeds::PerFrame.seed(seed, 2), seed.wrapping_add(2));
+    assert!(core.set_frame_seed_mode("random").is_err());
+}
+
+#[cfg(feature = "gif")]
+#[wasm_bindgen_test]
+fn gif_animation() {
+    let mut core = Core::default();
+    let mut animation = Animation::new(3);
+    animation.set_format("gif").unwrap();
+
+    let output = core
+        .animate(&images::VALID_IMAGE.to_vec(), &animation)
+        .unwrap();
+    assert_eq!(Format::detect(&output), Some(Format::Gif));
+}
+
+#[cfg(feature = "gif")]
+#[wasm_bindgen_test]
+fn gif_input() {
+    let mut core = Core::default();
+    let mut animation = Animation::new(3);
+    animation.set_format("gif").unwrap();
+
+    let image = core
+        .animate(&images::VALID_IMAGE.to_vec(), &animation)
+        .unwrap();
+    core.set_frame_seed_mode("per-frame").unwrap();
+
+    let output = core.pixelmosh(&image).unwrap();
+    assert_eq!(Format::detect(&output), Some(Format::Gif));
+}
+
+#[cfg(feature = "gif")]
+#[wasm_bindgen_test]
+fn gif_input_repeat() {
+    use image::codecs::gif::{GifEncoder, Repeat};
+
+    let frame = |shade: u8| {
+        image::Frame::new(image::RgbaImage::from_fn(16, 16, |x, y| {
+            image::Rgba([(x * 15) as u8, (y * 15) as u8, shade, 255])
+        }))
+    };
+
+    for (repeat, expected) in [
+        (Repeat::Finite(0), gif::Repeat::Finite(0)),
+        (Repeat::Finite(3), gif::Repeat::Finite(3)),
+        (Repeat::Infinite, gif::Repeat::Infinite),
+    ] {
+        let mut image = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut image);
+            encoder.set_repeat(repeat).unwrap();
+            encoder.encode_frames([frame(0), frame(255)]).unwrap();
+        }
+
+        let output = Core::default().pixelmosh(&image).unwrap();
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(std::io::Cursor::new(output))
+            .unwrap();
+        decoder.next_frame_info().unwrap();
+        assert_eq!(decoder.repeat(), expected);
+    }
+}
@@ -0,0 +1,99 @@
+#![cfg(target_arch = "wasm32")]
+
+extern crate wasm_bindgen_test;
+use wasm_bindgen_test::*;
+
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, prelude::*};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, DedicatedWorkerGlobalScope, MessageEvent, MessageEventInit, Url};
+
+use std::{cell::RefCell, rc::Rc};
+
+use webpixels::{AsyncCore, Core, start_worker};
+
+pub mod images;
+
+wasm_bindgen_test_configure!(run_in_dedicated_worker);
+
+fn get(target: &JsValue, key: &str) -> JsValue {
+    Reflect::get(target, &key.into()).unwrap()
+}
+
+fn request(scope: &DedicatedWorkerGlobalScope, id: u32, action: &str, image: &[u8]) -> JsValue {
+    let request = Object::new();
+    Reflect::set(&request, &"id".into(), &id.into()).unwrap();
+    Reflect::set(&request, &"action".into(), &action.into()).unwrap();
+    Reflect::set(
+        &request,
+        &"options".into(),
+        &Core::default().options_json().into(),
+    )
+    .unwrap();
+    Reflect::set(&request, &"image".into(), &Uint8Array::from(image)).unwrap();
+
+    let init = MessageEventInit::new();
+    init.set_data(&request);
+    let event = MessageEvent::new_with_event_init_dict("message", &init).unwrap();
+
+    // Replies are captured instead of being posted to the test runner
+    let reply = Rc::new(RefCell::new(JsValue::UNDEFINED));
+    let captured = Rc::clone(&reply);
+    let post_message = Closure::<dyn FnMut(JsValue)>::new(move |message| {
+        *captured.borrow_mut() = message;
+    });
+
+    Reflect::set(scope, &"postMessage".into(), post_message.as_ref()).unwrap();
+    scope.dispatch_event(&event).unwrap();
+    Reflect::delete_property(scope, &"postMessage".into()).unwrap();
+
+    reply.take()
+}
+
+#[wasm_bindgen_test]
+fn worker_requests() {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let runner = scope.onmessage();
+
+    start_worker().unwrap();
+    assert!(scope.onmessage().is_some());
+
+    let moshed = request(&scope, 7, "pixelmosh", images::VALID_IMAGE);
+    assert_eq!(get(&moshed, "id"), 7);
+    let output = Uint8Array::new(&get(&moshed, "output")).to_vec();
+    assert_eq!(output[..4], [0x89, b'P', b'N', b'G']);
+
+    let remoshed = request(&scope, 8, "remosh", &[]);
+    assert_eq!(Uint8Array::new(&get(&remoshed, "output")).to_vec(), output);
+
+    let failed = request(&scope, 9, "load", &[1, 2, 3]);
+    assert!(get(&failed, "output").is_undefined());
+    assert_eq!(get(&get(&failed, "error"), "kind"), "corrupt-input");
+
+    scope.set_onmessage(runner.as_ref());
+}
+
+#[wasm_bindgen_test]
+async fn terminated_calls_reject() {
+    let script = Blob::new_with_str_sequence(&Array::of1(&"".into())).unwrap();
+    let url = Url::create_object_url_with_blob(&script).unwrap();
+    let worker = AsyncCore::new(&url).unwrap();
+
+    worker.terminate();
+
+    for call in [
+        worker.remosh(&Core::default()),
+        worker.load(images::VALID_IMAGE),
+    ] {
+        let error = JsFuture::from(call).await.unwrap_err();
+        assert_eq!(get(&error, "message"), "Worker was terminated");
+    }
+
+    assert!(worker.cancel().is_ok());
+    let error = JsFuture::from(worker.remosh(&Core::default()))
+        .await
+        .unwrap_err();
+    assert_eq!(get(&error, "message"), "Worker was terminated");
+
+    Url::revoke_object_url(&url).unwrap();
+}
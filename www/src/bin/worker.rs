@@ -0,0 +1,3 @@
+pub fn main() {
+    webpixels::start_worker().expect("Can not start worker");
+}
@@ -0,0 +1,239 @@
+use js_sys::{Array, Object, Promise, Reflect, Uint8Array};
+use seed::{prelude::*, wasm_bindgen_futures::JsFuture};
+use web_sys::{
+    IdbDatabase, IdbObjectStore, IdbObjectStoreParameters, IdbRequest, IdbTransactionMode,
+};
+
+use webpixels::Recipe;
+
+const DATABASE: &str = "pixelmosh";
+const STORE: &str = "gallery";
+const QUOTA_KEY: &str = "pixelmosh:gallery-quota";
+/// Name of records stored without one
+const DEFAULT_NAME: &str = "image";
+
+/// Default storage quota in megabytes
+pub const DEFAULT_QUOTA: u32 = 50;
+/// Quota step of the settings buttons in megabytes
+pub const QUOTA_STEP: u32 = 10;
+
+/// Stored image and the settings that produced it
+#[derive(Clone)]
+pub struct Record {
+    /// Key assigned by the database
+    pub id: u32,
+    /// Creation time in milliseconds since the epoch
+    pub created: f64,
+    /// Source file name without extension
+    pub name: String,
+    pub recipe: Recipe,
+    pub output: Vec<u8>,
+}
+
+/// Record shown in the gallery panel
+pub struct Item {
+    pub record: Record,
+    /// Object URL of the output
+    pub url: String,
+}
+
+/// Images kept across reloads, oldest first
+pub struct Gallery {
+    pub db: Option<IdbDatabase>,
+    pub items: Vec<Item>,
+    /// Storage quota in megabytes
+    pub quota: u32,
+}
+
+impl Record {
+    pub fn new(name: String, recipe: Recipe, output: Vec<u8>) -> Self {
+        Self {
+            id: 0,
+            created: js_sys::Date::now(),
+            name,
+            recipe,
+            output,
+        }
+    }
+
+    /// Converts to a stored object, the key is left to the database
+    fn to_js(&self) -> Result<Object, JsValue> {
+        let object = Object::new();
+        Reflect::set(&object, &"created".into(), &self.created.into())?;
+        Reflect::set(&object, &"name".into(), &self.name.as_str().into())?;
+        Reflect::set(&object, &"recipe".into(), &self.recipe.to_json().into())?;
+        Reflect::set(
+            &object,
+            &"output".into(),
+            &Uint8Array::from(self.output.as_slice()),
+        )?;
+
+        Ok(object)
+    }
+
+    /// Reads a stored object, records with an invalid recipe are skipped
+    fn from_js(value: &JsValue) -> Option<Self> {
+        let field = |name: &str| Reflect::get(value, &name.into()).ok();
+
+        Some(Self {
+            id: field("id")?.as_f64()? as u32,
+            created: field("created")?.as_f64()?,
+            name: field("name")
+                .and_then(|name| name.as_string())
+                .unwrap_or_else(|| DEFAULT_NAME.to_string()),
+            recipe: Recipe::from_json(&field("recipe")?.as_string()?).ok()?,
+            output: Uint8Array::new(&field("output")?).to_vec(),
+        })
+    }
+}
+
+impl Default for Gallery {
+    fn default() -> Self {
+        Self {
+            db: None,
+            items: Vec::new(),
+            quota: load_quota(),
+        }
+    }
+}
+
+impl Gallery {
+    /// Total size of stored images in bytes
+    pub fn size(&self) -> usize {
+        self.items.iter().map(|item| item.record.output.len()).sum()
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Item> {
+        self.items.iter().find(|item| item.record.id == id)
+    }
+
+    /// Ids of the oldest items that do not fit into the quota
+    pub fn over_quota(&self) -> Vec<u32> {
+        let quota = self.quota as usize * 1024 * 1024;
+        let mut size = 0;
+
+        self.items
+            .iter()
+            .rev()
+            .filter(|item| {
+                size += item.record.output.len();
+                size > quota
+            })
+            .map(|item| item.record.id)
+            .collect()
+    }
+
+    /// Removes items and returns them
+    pub fn remove(&mut self, ids: &[u32]) -> Vec<Item> {
+        let (removed, kept) = std::mem::take(&mut self.items)
+            .into_iter()
+            .partition(|item| ids.contains(&item.record.id));
+
+        self.items = kept;
+
+        removed
+    }
+
+    pub fn set_quota(&mut self, quota: u32) {
+        self.quota = quota.max(QUOTA_STEP);
+        save_quota(self.quota);
+    }
+}
+
+/// Opens the database and reads all records
+pub async fn open() -> Result<(IdbDatabase, Vec<Record>), JsValue> {
+    let factory = web_sys::window()
+        .ok_or("No window")?
+        .indexed_db()?
+        .ok_or("IndexedDB is not available")?;
+
+    let request = factory.open_with_u32(DATABASE, 1)?;
+    let upgrade = request.clone();
+    let on_upgrade = Closure::once_into_js(move || {
+        if let Ok(db) = upgrade.result() {
+            let params = IdbObjectStoreParameters::new();
+            params.set_key_path(&"id".into());
+            params.set_auto_increment(true);
+
+            let db: IdbDatabase = db.unchecked_into();
+            db.create_object_store_with_optional_parameters(STORE, &params)
+                .expect("Can not create gallery store");
+        }
+    });
+    request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+
+    let db: IdbDatabase = resolve(&request).await?.unchecked_into();
+    let records = resolve(&store(&db, IdbTransactionMode::Readonly)?.get_all()?).await?;
+    let records = Array::from(&records)
+        .iter()
+        .filter_map(|record| Record::from_js(&record))
+        .collect();
+
+    Ok((db, records))
+}
+
+/// Stores a record and returns it with its key
+pub async fn add(db: IdbDatabase, mut record: Record) -> Result<Record, JsValue> {
+    let store = store(&db, IdbTransactionMode::Readwrite)?;
+    let id = resolve(&store.add(&record.to_js()?.into())?).await?;
+
+    record.id = id.as_f64().ok_or("Invalid gallery key")? as u32;
+
+    Ok(record)
+}
+
+pub async fn delete(db: IdbDatabase, ids: Vec<u32>) -> Result<(), JsValue> {
+    for id in ids {
+        let store = store(&db, IdbTransactionMode::Readwrite)?;
+        resolve(&store.delete(&id.into())?).await?;
+    }
+
+    Ok(())
+}
+
+fn store(db: &IdbDatabase, mode: IdbTransactionMode) -> Result<IdbObjectStore, JsValue> {
+    db.transaction_with_str_and_mode(STORE, mode)?
+        .object_store(STORE)
+}
+
+/// Waits for a request to finish
+async fn resolve(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let success = request.clone();
+        let on_success = Closure::once_into_js(move || {
+            let result = success.result().unwrap_or_default();
+            resolve.call1(&JsValue::NULL, &result).unwrap_or_default();
+        });
+
+        let failure = request.clone();
+        let on_error = Closure::once_into_js(move || {
+            let error = failure.error().ok().flatten().map(JsValue::from);
+            reject
+                .call1(&JsValue::NULL, &error.unwrap_or_default())
+                .unwrap_or_default();
+        });
+
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+        request.set_onerror(Some(on_error.unchecked_ref()));
+    });
+
+    JsFuture::from(promise).await
+}
+
+fn load_quota() -> u32 {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .and_then(|storage| storage.get_item(QUOTA_KEY).ok().flatten())
+        .and_then(|quota| quota.parse().ok())
+        .unwrap_or(DEFAULT_QUOTA)
+}
+
+fn save_quota(quota: u32) {
+    if let Some(storage) =
+        web_sys::window().and_then(|window| window.local_storage().ok().flatten())
+    {
+        storage
+            .set_item(QUOTA_KEY, &quota.to_string())
+            .unwrap_or_default();
+    }
+}
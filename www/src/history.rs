@@ -0,0 +1,111 @@
+use webpixels::Options;
+
+/// Number of kept results, pinned entries do not count
+pub const HISTORY_SIZE: usize = 16;
+
+/// Processed image kept for undo and redo
+pub struct Entry {
+    /// Source file name without extension
+    pub name: String,
+    /// Settings that produced the image
+    pub options: Options,
+    pub output: Vec<u8>,
+    /// Object URL of the output, also used for the thumbnail
+    pub url: String,
+    /// Pinned entries are never evicted
+    pub pinned: bool,
+}
+
+/// Bounded undo stack of processed images
+#[derive(Default)]
+pub struct History {
+    entries: Vec<Entry>,
+    current: usize,
+}
+
+impl Entry {
+    pub fn new(name: String, options: Options, output: Vec<u8>, url: String) -> Self {
+        Self {
+            name,
+            options,
+            output,
+            url,
+            pinned: false,
+        }
+    }
+}
+
+impl History {
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn current(&self) -> Option<&Entry> {
+        self.entries.get(self.current)
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.current > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.current + 1 < self.entries.len()
+    }
+
+    /// Adds a new result and returns evicted entries
+    ///
+    /// Redo entries are dropped unless they are pinned.
+    pub fn push(&mut self, entry: Entry) -> Vec<Entry> {
+        let mut evicted = Vec::new();
+
+        if !self.entries.is_empty() {
+            let redo = self.entries.split_off(self.current + 1);
+            let (pinned, dropped): (Vec<_>, Vec<_>) = redo.into_iter().partition(|e| e.pinned);
+
+            self.entries.extend(pinned);
+            evicted.extend(dropped);
+        }
+
+        self.entries.push(entry);
+        self.current = self.entries.len() - 1;
+
+        // The newest entry is never the oldest unpinned one
+        while self.entries.iter().filter(|entry| !entry.pinned).count() > HISTORY_SIZE {
+            let Some(index) = self.entries.iter().position(|entry| !entry.pinned) else {
+                break;
+            };
+
+            evicted.push(self.entries.remove(index));
+            self.current -= 1;
+        }
+
+        evicted
+    }
+
+    pub fn undo(&mut self) -> Option<&Entry> {
+        self.select(self.current.checked_sub(1)?)
+    }
+
+    pub fn redo(&mut self) -> Option<&Entry> {
+        self.select(self.current + 1)
+    }
+
+    pub fn select(&mut self, index: usize) -> Option<&Entry> {
+        if index < self.entries.len() {
+            self.current = index;
+        }
+
+        self.entries.get(index)
+    }
+
+    /// Pins or unpins the current entry
+    pub fn toggle_pin(&mut self) {
+        if let Some(entry) = self.entries.get_mut(self.current) {
+            entry.pinned = !entry.pinned;
+        }
+    }
+}
@@ -1,207 +1,478 @@
 use gloo_console::log;
-use js_sys::{Array, Uint8Array};
-use seed::{attrs, button, div, img, input, prelude::*, style, wasm_bindgen_futures};
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use seed::{
+    IF, attrs, button, div, img, input, option, prelude::*, select, style, wasm_bindgen_futures,
+};
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{self, Blob, BlobPropertyBag, File};
+use web_sys::{
+    self, Blob, BlobPropertyBag, Clipboard, ClipboardEvent, ClipboardItem, DataTransfer, DragEvent,
+    File, IdbDatabase,
+};
 
-use webpixels::Core;
+use webpixels::{AsyncCore, Core, Error, Options, Recipe, decode::Format};
+
+use gallery::{Gallery, Item, QUOTA_STEP, Record};
+use history::{Entry, History};
+use settings::{SETTINGS, Setting};
+
+mod gallery;
+mod history;
+mod settings;
+
+fn init(_: Url, orders: &mut impl Orders<Msg>) -> Model {
+    let worker = AsyncCore::new("./worker_loader.js").expect("Can not start worker");
+    let sender = orders.msg_sender();
+    let progress = Closure::<dyn Fn(JsValue, f64)>::new(move |_, percent: f64| {
+        sender(Some(Msg::Progress(percent as u8)));
+    });
+
+    worker.set_progress(Some(progress.as_ref().unchecked_ref::<Function>().clone()));
+    progress.forget();
+
+    orders.perform_cmd(async { Msg::GalleryOpened(gallery::open().await) });
+    orders.stream(streams::window_event(Ev::KeyDown, |event| {
+        shortcut(&event.unchecked_into())
+    }));
+
+    // The whole page accepts dropped and pasted images
+    orders.stream(streams::window_event(Ev::DragOver, |event| {
+        event.prevent_default();
+    }));
+    orders.stream(streams::window_event(Ev::Drop, |event| {
+        event.prevent_default();
+        let event: DragEvent = event.unchecked_into();
+
+        image_file(event.data_transfer()).map(|file| Msg::FileChanged(Some(file)))
+    }));
+    orders.stream(streams::window_event(Ev::Paste, |event| {
+        let event: ClipboardEvent = event.unchecked_into();
+        let file = image_file(event.clipboard_data())?;
+        event.prevent_default();
+
+        Some(Msg::FileChanged(Some(file)))
+    }));
+
+    let core = Core::default();
 
-fn init(_: Url, _: &mut impl Orders<Msg>) -> Model {
     Model {
+        busy: false,
         controls: false,
-        image_view: "".to_string(),
-        core: Core::default(),
-        storage: vec![0, 0],
+        error: None,
+        file_name: DEFAULT_NAME.to_string(),
+        gallery: Gallery::default(),
+        history: History::default(),
+        last_seed: core.seed(),
+        moshing: None,
+        name_template: NAME_TEMPLATE.to_string(),
+        remosh: 0,
+        remosh_queued: false,
+        core,
+        progress: 0,
+        seed_locked: false,
+        worker,
         storage_active: false,
     }
 }
 
+/// Download name, `{name}` is the source file name, `{seed}` and option
+/// names such as `{pixelation}` are replaced with their values
+const NAME_TEMPLATE: &str = "{name}_mosh_s{seed}";
+const DEFAULT_NAME: &str = "image";
+/// Delay of the live re-mosh after a setting changes, in milliseconds
+const REMOSH_DELAY: u32 = 300;
+
 struct Model {
+    busy: bool,
     controls: bool,
-    image_view: String,
+    /// Kind and message of the last failed mosh or copy
+    error: Option<String>,
+    /// Source file name without extension
+    file_name: String,
+    gallery: Gallery,
+    history: History,
+    /// Seed of the displayed image
+    last_seed: u64,
+    /// Settings of the running job
+    moshing: Option<Options>,
+    name_template: String,
+    /// Latest scheduled live re-mosh, earlier ones are dropped
+    remosh: u32,
+    /// Live re-mosh waits for the running job
+    remosh_queued: bool,
     core: Core,
-    storage: Vec<u8>,
+    progress: u8,
+    /// Keeps the seed between runs
+    seed_locked: bool,
+    worker: AsyncCore,
     storage_active: bool,
 }
 
 enum Msg {
+    Cancel,
+    Cancelled,
     ControlsRequested,
-    Convert(Vec<u8>),
+    Copy,
+    CopyFailed(JsValue),
     Download,
+    Failed(JsValue),
     FileChanged(Option<File>),
+    FileLoaded,
     FileStore(JsValue),
-    FileView(Uint8Array),
+    GalleryDelete(u32),
+    GalleryDownload(u32),
+    GalleryError(JsValue),
+    GalleryOpen(u32),
+    GalleryOpened(Result<(IdbDatabase, Vec<Record>), JsValue>),
+    GalleryRemosh(u32),
+    GallerySaved(Box<Record>),
+    HistorySelect(usize),
+    Moshed(Uint8Array),
+    NameTemplateChanged(String),
+    OutputFormat(String),
+    Pin,
     PixelMosh,
-    Reload,
+    Progress(u8),
+    Redo,
+    Remosh(u32),
+    SeedChanged(String),
+    SeedLock,
+    Undo,
+    WorkerFailed(JsValue),
     // Options
     Ansi,
-    DecMinRate,
-    IncMinRate,
-    DecMaxRate,
-    IncMaxRate,
-    DecPixelation,
-    IncPixelation,
-    DecLineShift,
-    IncLineShift,
-    DecReverse,
-    IncReverse,
-    DecFlip,
-    IncFlip,
-    DecChannelSwap,
-    IncChannelSwap,
-    DecChannelShift,
-    IncChannelShift,
+    SettingChanged(usize, String),
+    SettingReset(usize),
+    DecQuota,
+    IncQuota,
 }
 
 fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
     match msg {
+        Msg::Cancel => {
+            if let Err(error) = model.worker.cancel() {
+                orders.send_msg(Msg::Failed(error.into()));
+            }
+        }
+        Msg::Cancelled => {
+            model.busy = false;
+            model.progress = 0;
+            model.moshing = None;
+
+            log!["CANCELLED"];
+        }
         Msg::ControlsRequested => model.controls = true,
-        Msg::Convert(input) => {
-            let array = Uint8Array::new(&unsafe { Uint8Array::view(&input) }.into());
-            orders.send_msg(Msg::FileView(array));
+        Msg::Copy => {
+            let Some(entry) = model.history.current() else {
+                return;
+            };
+
+            let writing = match write_clipboard(&blob(&entry.output)) {
+                Ok(writing) => writing,
+                Err(error) => {
+                    orders.send_msg(Msg::CopyFailed(error));
+                    return;
+                }
+            };
+
+            orders.perform_cmd(async move {
+                match JsFuture::from(writing).await {
+                    Ok(_) => {
+                        log!["COPIED"];
+                        None
+                    }
+                    Err(error) => Some(Msg::CopyFailed(error)),
+                }
+            });
+        }
+        Msg::CopyFailed(error) => {
+            let text = error_text(&error);
+            log!["COPY ERROR", &text];
+            model.error = Some(text);
         }
         Msg::Download => {
-            let window = web_sys::window().unwrap();
-            window.open_with_url(&model.image_view).unwrap();
+            if let Some(entry) = model.history.current() {
+                let name = file_name(&model.name_template, &entry.name, &entry.options);
+                download(&entry.url, &name, &entry.output);
+            }
+        }
+        Msg::Failed(error) => {
+            model.busy = false;
+            model.progress = 0;
+            model.moshing = None;
+            model.remosh_queued = false;
+
+            let text = error_text(&error);
+            log!["ERROR", &text];
+            model.error = Some(text);
         }
         Msg::FileChanged(file) => {
-            model.image_view.clear();
-            model.storage.clear();
+            let Some(file) = file else {
+                return;
+            };
 
-            orders.perform_cmd(async move {
-                let image = JsFuture::from(file.unwrap().array_buffer())
-                    .await
-                    .expect("Can not read file");
+            let name = file.name();
+            model.file_name = name
+                .rsplit_once('.')
+                .map_or(name.as_str(), |(stem, _)| stem)
+                .to_string();
+            model.busy = true;
 
-                Msg::FileStore(image)
+            orders.perform_cmd(async move {
+                match JsFuture::from(file.array_buffer()).await {
+                    Ok(image) => Msg::FileStore(image),
+                    Err(error) => Msg::Failed(error),
+                }
             });
         }
-        Msg::FileStore(file) => {
-            let array = Uint8Array::new(&file);
-            let bytes: Vec<u8> = array.to_vec();
-
-            model.storage = bytes;
+        Msg::FileLoaded => {
+            model.busy = false;
             model.storage_active = true;
 
             log!["FILE LOADED"];
             orders.send_msg(Msg::PixelMosh);
         }
-        Msg::FileView(file) => {
-            let array = Array::new();
-            array.push(&file.buffer());
-
-            let image = JsValue::from(array);
-            let blob_prop = BlobPropertyBag::new();
-            blob_prop.set_type("image/png");
+        Msg::FileStore(file) => {
+            let array = Uint8Array::new(&file);
+            let bytes: Vec<u8> = array.to_vec();
+            let loading = model.worker.load(&bytes);
 
-            let blob = Blob::new_with_u8_array_sequence_and_options(&image, &blob_prop).unwrap();
+            // The worker drops its previous source
+            model.storage_active = false;
+            model.error = None;
 
-            let url = web_sys::Url::create_object_url_with_blob(&blob).unwrap();
-            model.image_view = url;
+            orders.perform_cmd(async move {
+                match JsFuture::from(loading).await {
+                    Ok(_) => Msg::FileLoaded,
+                    Err(error) => rejected(error),
+                }
+            });
         }
-        Msg::PixelMosh => {
-            log!(model.core.seed());
-            match model.core.pixelmosh(&model.storage) {
-                Ok(moshed) => orders.send_msg(Msg::Convert(moshed)),
-                Err(_) => orders.send_msg(Msg::Reload),
-            };
+        Msg::GalleryDelete(id) => delete_from_gallery(model, orders, vec![id]),
+        Msg::GalleryDownload(id) => {
+            if let Some(item) = model.gallery.get(id) {
+                let record = &item.record;
+                let name = file_name(&model.name_template, &record.name, &record.recipe.options);
+                download(&item.url, &name, &record.output);
+            }
+        }
+        Msg::GalleryError(error) | Msg::GalleryOpened(Err(error)) => {
+            log!["GALLERY ERROR", error];
+        }
+        Msg::GalleryOpen(id) => {
+            if let Some(item) = model.gallery.get(id) {
+                let options = item.record.recipe.options.clone();
+                let name = item.record.name.clone();
+                let output = item.record.output.clone();
+                let url = object_url(&output);
 
-            log!["PIXELMOSH: DONE"];
-            model.core.new_seed();
+                push_history(model, Entry::new(name, options.clone(), output, url));
+                restore(model, Some(options));
+            }
         }
-        Msg::Reload => {
-            log!["ERROR! RESTARTING..."];
-            Url::reload();
+        Msg::GalleryOpened(Ok((db, records))) => {
+            model.gallery.db = Some(db);
+            model.gallery.items = records
+                .into_iter()
+                .map(|record| Item {
+                    url: object_url(&record.output),
+                    record,
+                })
+                .collect();
+
+            enforce_quota(model, orders);
         }
-        Msg::Ansi => {
-            let value = !model.core.ansi();
-            model.core.set_ansi(value);
-            log!(model.core.ansi());
+        Msg::GalleryRemosh(id) => {
+            if model.busy {
+                return;
+            }
+
+            // The stored image becomes the new source
+            if let Some(item) = model.gallery.get(id) {
+                let image = Uint8Array::from(item.record.output.as_slice());
+                let options = item.record.recipe.options.clone();
+
+                model.file_name.clone_from(&item.record.name);
+                restore(model, Some(options));
+                model.busy = true;
+                orders.send_msg(Msg::FileStore(image.buffer().into()));
+            }
         }
-        Msg::DecMinRate => {
-            let value = model.core.min_rate() - 1;
-            model.core.set_min_rate(value.clamp(1, 100));
-            log!(model.core.min_rate());
+        Msg::GallerySaved(record) => {
+            model.gallery.items.push(Item {
+                url: object_url(&record.output),
+                record: *record,
+            });
+
+            enforce_quota(model, orders);
         }
-        Msg::IncMinRate => {
-            let value = model.core.min_rate() + 1;
-            model.core.set_min_rate(value.clamp(1, 100));
-            log!(model.core.min_rate());
+        Msg::HistorySelect(index) => {
+            let options = model
+                .history
+                .select(index)
+                .map(|entry| entry.options.clone());
+            restore(model, options);
         }
-        Msg::DecMaxRate => {
-            let value = model.core.max_rate() - 1;
-            model.core.set_max_rate(value.clamp(1, 100));
-            log!(model.core.max_rate());
+        Msg::Moshed(moshed) => {
+            model.busy = false;
+
+            let options = model.moshing.take().unwrap_or_else(|| model.core.options());
+            let output = moshed.to_vec();
+
+            if let Some(db) = model.gallery.db.clone() {
+                let record = Record::new(
+                    model.file_name.clone(),
+                    Recipe::new(options.clone()),
+                    output.clone(),
+                );
+
+                orders.perform_cmd(async move {
+                    match gallery::add(db, record).await {
+                        Ok(record) => Msg::GallerySaved(Box::new(record)),
+                        Err(error) => Msg::GalleryError(error),
+                    }
+                });
+            }
+
+            let url = object_url(&output);
+            push_history(
+                model,
+                Entry::new(model.file_name.clone(), options, output, url),
+            );
+
+            log!["PIXELMOSH: DONE"];
+
+            if std::mem::take(&mut model.remosh_queued) {
+                orders.send_msg(Msg::Remosh(model.remosh));
+            }
         }
-        Msg::IncMaxRate => {
-            let value = model.core.max_rate() + 1;
-            model.core.set_max_rate(value.clamp(1, 100));
-            log!(model.core.max_rate());
+        Msg::NameTemplateChanged(template) => model.name_template = template,
+        Msg::OutputFormat(format) => {
+            log_error(match format.as_str() {
+                "jpeg" => model.core.set_output_jpeg(None),
+                "webp" => model.core.set_output_webp(None),
+                "qoi" => model.core.set_output_qoi(),
+                _ => model.core.set_output_png("balanced", None),
+            });
+            log!(model.core.output_format());
+
+            // Downloads save results as moshed, the displayed one is moshed again
+            schedule_remosh(model, orders);
         }
-        Msg::DecPixelation => {
-            let value = model.core.pixelation() - 1;
-            model.core.set_pixelation(value.clamp(1, 255));
-            log!(model.core.pixelation());
+        Msg::Pin => model.history.toggle_pin(),
+        Msg::PixelMosh => {
+            if model.busy {
+                return;
+            }
+
+            model.busy = true;
+            model.progress = 0;
+            model.error = None;
+            log!(model.core.seed());
+
+            model.moshing = Some(model.core.options());
+
+            let moshing = model.worker.remosh(&model.core);
+            orders.perform_cmd(async move {
+                match JsFuture::from(moshing).await {
+                    Ok(moshed) => Msg::Moshed(Uint8Array::new(&moshed)),
+                    Err(error) => rejected(error),
+                }
+            });
+
+            model.last_seed = model.core.seed();
+
+            if !model.seed_locked {
+                model.core.new_seed();
+            }
         }
-        Msg::IncPixelation => {
-            let value = model.core.pixelation() + 1;
-            model.core.set_pixelation(value.clamp(1, 255));
-            log!(model.core.pixelation());
+        Msg::Progress(percent) => model.progress = percent,
+        Msg::Redo => {
+            let options = model.history.redo().map(|entry| entry.options.clone());
+            restore(model, options);
         }
-        Msg::DecLineShift => {
-            let value = model.core.line_shift() - 0.1;
-            model.core.set_line_shift(value.clamp(0.0, 1.0));
-            log!(model.core.line_shift());
+        Msg::Remosh(generation) => {
+            if generation != model.remosh || !model.storage_active {
+                return;
+            }
+
+            if model.busy {
+                model.remosh_queued = true;
+                return;
+            }
+
+            // Changes are shown on the displayed image
+            if !model.seed_locked {
+                model.core.set_seed(model.last_seed);
+            }
+            orders.send_msg(Msg::PixelMosh);
         }
-        Msg::IncLineShift => {
-            let value = model.core.line_shift() + 0.1;
-            model.core.set_line_shift(value.clamp(0.0, 1.0));
-            log!(model.core.line_shift());
+        Msg::SeedChanged(text) => {
+            let text = text.trim();
+
+            // Anything but a number is a phrase
+            match text.parse() {
+                Ok(seed) => model.core.set_seed(seed),
+                Err(_) => model.core.set_seed_phrase(text),
+            }
+
+            model.seed_locked = true;
+            log!(model.core.seed());
         }
-        Msg::DecReverse => {
-            let value = model.core.reverse() - 0.1;
-            model.core.set_reverse(value.clamp(0.0, 1.0));
-            log!(model.core.reverse());
+        Msg::SeedLock => {
+            model.seed_locked = !model.seed_locked;
+
+            if model.seed_locked {
+                model.core.set_seed(model.last_seed);
+            }
         }
-        Msg::IncReverse => {
-            let value = model.core.reverse() + 0.1;
-            model.core.set_reverse(value.clamp(0.0, 1.0));
-            log!(model.core.reverse());
+        Msg::Undo => {
+            let options = model.history.undo().map(|entry| entry.options.clone());
+            restore(model, options);
         }
-        Msg::DecFlip => {
-            let value = model.core.flip() - 0.1;
-            model.core.set_flip(value.clamp(0.0, 1.0));
-            log!(model.core.flip());
+        Msg::WorkerFailed(error) => {
+            // A fresh worker takes the next job
+            if let Err(error) = model.worker.cancel() {
+                log!["WORKER ERROR", error.to_string()];
+            }
+
+            orders.send_msg(Msg::Failed(error));
         }
-        Msg::IncFlip => {
-            let value = model.core.flip() + 0.1;
-            model.core.set_flip(value.clamp(0.0, 1.0));
-            log!(model.core.flip());
+        Msg::Ansi => {
+            let value = !model.core.ansi();
+            model.core.set_ansi(value);
+            log!(model.core.ansi());
         }
-        Msg::DecChannelSwap => {
-            let value = model.core.channel_swap() - 0.1;
-            model.core.set_channel_swap(value.clamp(0.0, 1.0));
-            log!(model.core.channel_swap());
+        Msg::SettingChanged(index, text) => {
+            let Ok(value) = text.trim().parse() else {
+                return;
+            };
+
+            let setting = &SETTINGS[index];
+            log_error(setting.set(&mut model.core, value));
+            log!(setting.label, setting.get(&model.core));
+            schedule_remosh(model, orders);
         }
-        Msg::IncChannelSwap => {
-            let value = model.core.channel_swap() + 0.1;
-            model.core.set_channel_swap(value.clamp(0.0, 1.0));
-            log!(model.core.channel_swap());
+        Msg::SettingReset(index) => {
+            let setting = &SETTINGS[index];
+            log_error(setting.reset(&mut model.core));
+            log!(setting.label, setting.get(&model.core));
+            schedule_remosh(model, orders);
         }
-        Msg::DecChannelShift => {
-            let value = model.core.channel_shift() - 0.1;
-            model.core.set_channel_shift(value.clamp(0.0, 1.0));
-            log!(model.core.channel_shift());
+        Msg::DecQuota => {
+            let quota = model.gallery.quota.saturating_sub(QUOTA_STEP);
+            model.gallery.set_quota(quota);
+            enforce_quota(model, orders);
         }
-        Msg::IncChannelShift => {
-            let value = model.core.channel_shift() + 0.1;
-            model.core.set_channel_shift(value.clamp(0.0, 1.0));
-            log!(model.core.channel_shift());
+        Msg::IncQuota => {
+            let quota = model.gallery.quota.saturating_add(QUOTA_STEP);
+            model.gallery.set_quota(quota);
         }
     }
 }
 
 fn view(model: &Model) -> Node<Msg> {
+    let pinned = model.history.current().is_some_and(|entry| entry.pinned);
+
     div![
         style![
             St::Display => "flex",
@@ -228,7 +499,7 @@ fn view(model: &Model) -> Node<Msg> {
                 attrs! {
                     At::Type => "file",
                     At::Id => "form-file",
-                    At::Accept => "image/png",
+                    At::Accept => "image/png,image/jpeg,image/webp,image/gif,image/bmp,.qoi",
                 }
             ],
             style![
@@ -236,7 +507,8 @@ fn view(model: &Model) -> Node<Msg> {
                 St::FlexDirection => "column",
                 St::AlignItems => "center",
             ],
-            if model.storage_active {
+            model.error.as_deref().map(view_error),
+            if model.storage_active || model.history.current().is_some() {
                 div![
                     div![
                         style![
@@ -247,13 +519,14 @@ fn view(model: &Model) -> Node<Msg> {
                         ],
                         img![
                             attrs! {
-                                At::Src => model.image_view
+                                At::Src => model.history.current().map_or("", |entry| entry.url.as_str())
                                 At::Width => "500px"
                             },
                             style![
                                 St::Border => [&px(7), "solid", "black"].join(" "),
                             ],
                         ],
+                        IF!(!model.history.entries().is_empty() => view_history(&model.history)),
                     ],
                     style![
                         St::Display => "flex",
@@ -262,12 +535,57 @@ fn view(model: &Model) -> Node<Msg> {
                     ],
                     div![
                         button![
-                            "MOSH",
+                            if model.busy {
+                                format!("MOSHING {}%", model.progress)
+                            } else {
+                                "MOSH".to_string()
+                            },
                             ev(Ev::Click, |_| Msg::PixelMosh),
+                            attrs! {
+                                At::Disabled => (model.busy || !model.storage_active).as_at_value(),
+                            },
+                            style![
+                                St::Padding => "4px",
+                            ],
+                        ],
+                        IF!(model.busy => button![
+                            "CANCEL",
+                            ev(Ev::Click, |_| Msg::Cancel),
+                            style![
+                                St::Padding => "4px",
+                            ],
+                        ]),
+                        button![
+                            "UNDO",
+                            ev(Ev::Click, |_| Msg::Undo),
+                            attrs! {
+                                At::Disabled => (!model.history.can_undo()).as_at_value(),
+                                At::Title => "Ctrl+Z",
+                            },
                             style![
                                 St::Padding => "4px",
                             ],
                         ],
+                        button![
+                            "REDO",
+                            ev(Ev::Click, |_| Msg::Redo),
+                            attrs! {
+                                At::Disabled => (!model.history.can_redo()).as_at_value(),
+                                At::Title => "Ctrl+Shift+Z",
+                            },
+                            style![
+                                St::Padding => "4px",
+                            ],
+                        ],
+                        button![
+                            "PIN",
+                            ev(Ev::Click, |_| Msg::Pin),
+                            style![
+                                St::Padding => "4px",
+                                St::Color => if pinned {"white"} else {"black"},
+                                St::BackgroundColor => if pinned {"green"} else {"gray"},
+                            ],
+                        ],
                         button![
                             "ANSI",
                             ev(Ev::Click, |_| Msg::Ansi),
@@ -277,9 +595,39 @@ fn view(model: &Model) -> Node<Msg> {
                                 St::BackgroundColor => if model.core.ansi() {"green"} else {"gray"},
                             ],
                         ],
+                        button![
+                            "COPY",
+                            ev(Ev::Click, |_| Msg::Copy),
+                            attrs! {
+                                At::Disabled => model.history.current().is_none().as_at_value(),
+                            },
+                            style![
+                                St::Padding => "4px",
+                            ],
+                        ],
                         button![
                             "DOWNLOAD",
                             ev(Ev::Click, |_| Msg::Download),
+                            attrs! {
+                                At::Disabled => model.history.current().is_none().as_at_value(),
+                            },
+                            style![
+                                St::Padding => "4px",
+                            ],
+                        ],
+                        "MOSH FORMAT",
+                        select![
+                            input_ev(Ev::Change, Msg::OutputFormat),
+                            ["png", "jpeg", "webp", "qoi"].map(|format| option![
+                                attrs! {
+                                    At::Value => format,
+                                    At::Selected => (model.core.output_format() == format).as_at_value(),
+                                },
+                                format.to_uppercase(),
+                            ]),
+                            attrs! {
+                                At::Title => "Output format of moshed images, the displayed one is moshed again",
+                            },
                             style![
                                 St::Padding => "4px",
                             ],
@@ -295,78 +643,53 @@ fn view(model: &Model) -> Node<Msg> {
                     ],
                     if model.controls {
                         div![
+                            view_settings(&model.core),
                             div![
                                 div![
-                                    "Min rate: ",
-                                    model.core.min_rate().to_string(),
+                                    "File name: ",
                                     style![St::MarginTop => "4px", St::MarginBottom => "4px"]
                                 ],
-                                button![ev(Ev::Click, |_| Msg::DecMinRate), "-"],
-                                button![ev(Ev::Click, |_| Msg::IncMinRate), "+"],
-                                div![
-                                    "Max rate: ",
-                                    model.core.max_rate().to_string(),
-                                    style![St::MarginTop => "4px", St::MarginBottom => "4px"]
+                                input![
+                                    input_ev(Ev::Change, Msg::NameTemplateChanged),
+                                    attrs! {
+                                        At::Type => "text",
+                                        At::Value => model.name_template,
+                                        At::Title => "{name}, {seed} and option names are replaced",
+                                    },
+                                    style![
+                                        St::FontFamily => "monospace",
+                                        St::Width => "20ch",
+                                    ],
                                 ],
-                                button![ev(Ev::Click, |_| Msg::DecMaxRate), "-"],
-                                button![ev(Ev::Click, |_| Msg::IncMaxRate), "+"],
                                 style![
                                     St::Padding => "4px",
                                 ],
                             ],
                             div![
                                 div![
-                                    "Pixelation: ",
-                                    model.core.pixelation().to_string(),
+                                    "Seed: ",
                                     style![St::MarginTop => "4px", St::MarginBottom => "4px"]
                                 ],
-                                button![ev(Ev::Click, |_| Msg::DecPixelation), "-"],
-                                button![ev(Ev::Click, |_| Msg::IncPixelation), "+"],
-                                div![
-                                    "Line shift: ",
-                                    float_to_str_trim(model.core.line_shift()),
-                                    style![St::MarginTop => "4px", St::MarginBottom => "4px"]
+                                input![
+                                    input_ev(Ev::Change, Msg::SeedChanged),
+                                    attrs! {
+                                        At::Type => "text",
+                                        At::Value => model.core.seed().to_string(),
+                                        At::Placeholder => "number or phrase",
+                                    },
+                                    style![
+                                        St::FontFamily => "monospace",
+                                        St::Width => "20ch",
+                                    ],
                                 ],
-                                button![ev(Ev::Click, |_| Msg::DecLineShift), "-"],
-                                button![ev(Ev::Click, |_| Msg::IncLineShift), "+"],
-                                style![
-                                    St::Padding => "4px",
-                                ],
-                            ],
-                            div![
-                                div![
-                                    "Reverse: ",
-                                    float_to_str_trim(model.core.reverse()),
-                                    style![St::MarginTop => "4px", St::MarginBottom => "4px"]
-                                ],
-                                button![ev(Ev::Click, |_| Msg::DecReverse), "-"],
-                                button![ev(Ev::Click, |_| Msg::IncReverse), "+"],
-                                div![
-                                    "Flip: ",
-                                    float_to_str_trim(model.core.flip()),
-                                    style![St::MarginTop => "4px", St::MarginBottom => "4px"]
-                                ],
-                                button![ev(Ev::Click, |_| Msg::DecFlip), "-"],
-                                button![ev(Ev::Click, |_| Msg::IncFlip), "+"],
-                                style![
-                                    St::Padding => "4px",
-                                ],
-                            ],
-                            div![
-                                div![
-                                    "Channel Swap: ",
-                                    float_to_str_trim(model.core.channel_swap()),
-                                    style![St::MarginTop => "4px", St::MarginBottom => "4px"]
-                                ],
-                                button![ev(Ev::Click, |_| Msg::DecChannelSwap), "-"],
-                                button![ev(Ev::Click, |_| Msg::IncChannelSwap), "+"],
-                                div![
-                                    "Channel Shift: ",
-                                    float_to_str_trim(model.core.channel_shift()),
-                                    style![St::MarginTop => "4px", St::MarginBottom => "4px"]
-                                ],
-                                button![ev(Ev::Click, |_| Msg::DecChannelShift), "-"],
-                                button![ev(Ev::Click, |_| Msg::IncChannelShift), "+"],
+                                div![button![
+                                    "LOCK",
+                                    ev(Ev::Click, |_| Msg::SeedLock),
+                                    style![
+                                        St::Color => if model.seed_locked {"white"} else {"black"},
+                                        St::BackgroundColor => if model.seed_locked {"green"} else {"gray"},
+                                    ],
+                                ]],
                                 style![
                                     St::Padding => "4px",
                                 ],
@@ -374,6 +697,8 @@ fn view(model: &Model) -> Node<Msg> {
                             style![
                                 St::Display => "flex",
                                 St::FlexDirection => "row",
+                                St::FlexWrap => "wrap",
+                                St::JustifyContent => "center",
                                 St::AlignItems => "center",
                                 St::TextAlign => "center",
                                 St::FontFamily => "monospace",
@@ -403,7 +728,7 @@ fn view(model: &Model) -> Node<Msg> {
                 ]
             } else {
                 div![
-                    "INSERT PNG FILE",
+                    "INSERT, DROP OR PASTE IMAGE FILE",
                     style![
                         St::Display => "flex",
                         St::FlexDirection => "column",
@@ -414,20 +739,398 @@ fn view(model: &Model) -> Node<Msg> {
                         St::FontSize => "medium",
                     ]
                 ]
-            }
+            },
+            IF!(model.gallery.db.is_some() => view_gallery(&model.gallery)),
         ]
     ]
 }
 
+fn view_error(error: &str) -> Node<Msg> {
+    div![
+        format!("ERROR! {error}"),
+        style![
+            St::Color => "white",
+            St::BackgroundColor => "red",
+            St::FontFamily => "monospace",
+            St::Padding => "4px",
+            St::Margin => "4px",
+        ],
+    ]
+}
+
+/// Sliders and numeric inputs of all numeric options
+fn view_settings(core: &Core) -> Node<Msg> {
+    div![
+        SETTINGS
+            .iter()
+            .enumerate()
+            .map(|(index, setting)| view_setting(index, setting, setting.get(core))),
+        style![
+            St::Display => "grid",
+            St::GridTemplateColumns => "auto auto auto auto",
+            St::AlignItems => "center",
+            St::Gap => "4px",
+            St::Padding => "4px",
+            St::TextAlign => "left",
+        ],
+    ]
+}
+
+fn view_setting(index: usize, setting: &Setting, value: f64) -> Vec<Node<Msg>> {
+    let limit = setting.limit;
+    let range = attrs! {
+        At::Min => limit.min,
+        At::Max => limit.max,
+        At::Step => limit.step,
+        At::Value => setting.format(value),
+    };
+
+    vec![
+        div![setting.label],
+        input![
+            input_ev(Ev::Input, move |text| Msg::SettingChanged(index, text)),
+            attrs! { At::Type => "range" },
+            range.clone(),
+        ],
+        input![
+            input_ev(Ev::Change, move |text| Msg::SettingChanged(index, text)),
+            attrs! { At::Type => "number" },
+            range,
+            style![
+                St::FontFamily => "monospace",
+                St::Width => "7ch",
+            ],
+        ],
+        button![
+            "RESET",
+            ev(Ev::Click, move |_| Msg::SettingReset(index)),
+            attrs! {
+                At::Disabled => (value == setting.default).as_at_value(),
+                At::Title => format!("Default: {}", setting.format(setting.default)),
+            },
+        ],
+    ]
+}
+
+/// Stored images, newest first
+fn view_gallery(gallery: &Gallery) -> Node<Msg> {
+    let megabytes = gallery.size() as f64 / (1024.0 * 1024.0);
+
+    div![
+        div![
+            format!("GALLERY: {megabytes:.1} / {} MB", gallery.quota),
+            button![ev(Ev::Click, |_| Msg::DecQuota), "-"],
+            button![ev(Ev::Click, |_| Msg::IncQuota), "+"],
+            style![
+                St::Display => "flex",
+                St::AlignItems => "center",
+                St::JustifyContent => "center",
+                St::Gap => "3px",
+            ],
+        ],
+        div![
+            gallery.items.iter().rev().map(|item| {
+                let id = item.record.id;
+
+                div![
+                    img![
+                        attrs! {
+                            At::Src => item.url
+                            At::Width => "96px"
+                            At::Title => js_sys::Date::new(&item.record.created.into())
+                                .to_locale_string("default", &JsValue::UNDEFINED)
+                                .as_string()
+                                .unwrap_or_default(),
+                        },
+                        style![
+                            St::Border => [&px(3), "solid", "black"].join(" "),
+                        ],
+                    ],
+                    div![
+                        button![ev(Ev::Click, move |_| Msg::GalleryOpen(id)), "OPEN"],
+                        button![ev(Ev::Click, move |_| Msg::GalleryRemosh(id)), "REMOSH"],
+                    ],
+                    div![
+                        button![ev(Ev::Click, move |_| Msg::GalleryDownload(id)), "SAVE"],
+                        button![ev(Ev::Click, move |_| Msg::GalleryDelete(id)), "DELETE"],
+                    ],
+                    style![
+                        St::Display => "flex",
+                        St::FlexDirection => "column",
+                        St::AlignItems => "center",
+                        St::Gap => "2px",
+                    ],
+                ]
+            }),
+            style![
+                St::Display => "flex",
+                St::FlexWrap => "wrap",
+                St::JustifyContent => "center",
+                St::Gap => "6px",
+                St::PaddingTop => "6px",
+            ],
+        ],
+        style![
+            St::FontFamily => "monospace",
+            St::FontSize => "small",
+            St::Padding => "4px",
+            St::Margin => "10px",
+            St::MaxWidth => "514px",
+            St::Border => [&px(3), "dashed", "black"].join(" "),
+        ],
+    ]
+}
+
+/// Thumbnails of kept results, the current one is outlined
+fn view_history(history: &History) -> Node<Msg> {
+    div![
+        history.entries().iter().enumerate().map(|(index, entry)| {
+            let border = if index == history.current_index() {
+                "red"
+            } else if entry.pinned {
+                "green"
+            } else {
+                "black"
+            };
+
+            img![
+                ev(Ev::Click, move |_| Msg::HistorySelect(index)),
+                attrs! {
+                    At::Src => entry.url
+                    At::Width => "48px"
+                    At::Title => format!("{} KB", entry.output.len().div_ceil(1024)),
+                },
+                style![
+                    St::Border => [&px(3), "solid", border].join(" "),
+                    St::Cursor => "pointer",
+                ],
+            ]
+        }),
+        style![
+            St::Display => "flex",
+            St::FlexWrap => "wrap",
+            St::JustifyContent => "center",
+            St::Gap => "3px",
+            St::MaxWidth => "514px",
+            St::PaddingTop => "6px",
+        ],
+    ]
+}
+
+/// Kind and message of a rejected job
+fn error_text(error: &JsValue) -> String {
+    let field = |name: &str| {
+        Reflect::get(error, &name.into())
+            .ok()
+            .and_then(|value| value.as_string())
+    };
+    let message = field("message").unwrap_or_else(|| format!("{error:?}"));
+
+    match field("kind") {
+        Some(kind) => format!("{}: {message}", kind.to_uppercase()),
+        None => message,
+    }
+}
+
 pub fn main() {
     App::start("Pixelmosh", init, update, view);
 }
 
-fn float_to_str_trim(float: f64) -> String {
-    if float != 1.0 && float != 0.0 {
-        let str = float.to_string();
-        str[..3].to_string()
+/// Rejected settings are kept unchanged
+fn log_error(result: Result<(), Error>) {
+    if let Err(error) = result {
+        log!(error.to_string());
+    }
+}
+
+/// Shows a history entry and continues from its settings
+fn restore(model: &mut Model, options: Option<Options>) {
+    let Some(options) = options else {
+        return;
+    };
+
+    log_error(model.core.set_options(&options));
+    model.last_seed = options.seed;
+
+    if !model.seed_locked {
+        model.core.new_seed();
+    }
+}
+
+/// Re-moshes once settings stop changing
+fn schedule_remosh(model: &mut Model, orders: &mut impl Orders<Msg>) {
+    model.remosh = model.remosh.wrapping_add(1);
+
+    let generation = model.remosh;
+    orders.perform_cmd(cmds::timeout(REMOSH_DELAY, move || Msg::Remosh(generation)));
+}
+
+/// Adds a result to the history and releases evicted entries
+fn push_history(model: &mut Model, entry: Entry) {
+    for entry in model.history.push(entry) {
+        web_sys::Url::revoke_object_url(&entry.url).unwrap();
+    }
+}
+
+/// Deletes the oldest gallery images over the quota
+fn enforce_quota(model: &mut Model, orders: &mut impl Orders<Msg>) {
+    let ids = model.gallery.over_quota();
+
+    if !ids.is_empty() {
+        log!["GALLERY: QUOTA EXCEEDED"];
+        delete_from_gallery(model, orders, ids);
+    }
+}
+
+fn delete_from_gallery(model: &mut Model, orders: &mut impl Orders<Msg>, ids: Vec<u32>) {
+    for item in model.gallery.remove(&ids) {
+        web_sys::Url::revoke_object_url(&item.url).unwrap();
+    }
+
+    if let Some(db) = model.gallery.db.clone() {
+        orders.perform_cmd(
+            async move { gallery::delete(db, ids).await.err().map(Msg::GalleryError) },
+        );
+    }
+}
+
+/// Saves an image through a temporary link
+fn download(url: &str, name: &str, output: &[u8]) {
+    let anchor: web_sys::HtmlAnchorElement = seed::document()
+        .create_element("a")
+        .unwrap()
+        .unchecked_into();
+
+    anchor.set_href(url);
+    anchor.set_download(&format!("{name}.{}", file_type(output).1));
+    anchor.click();
+}
+
+/// Fills in [`NAME_TEMPLATE`] placeholders, path separators are replaced
+fn file_name(template: &str, name: &str, options: &Options) -> String {
+    let values = [
+        ("name", name.to_string()),
+        ("seed", options.seed.to_string()),
+        ("min_rate", options.min_rate.to_string()),
+        ("max_rate", options.max_rate.to_string()),
+        ("pixelation", options.pixelation.to_string()),
+        ("line_shift", options.line_shift.to_string()),
+        ("reverse", options.reverse.to_string()),
+        ("flip", options.flip.to_string()),
+        ("channel_swap", options.channel_swap.to_string()),
+        ("channel_shift", options.channel_shift.to_string()),
+    ];
+
+    let name = values
+        .iter()
+        .fold(template.to_string(), |name, (key, value)| {
+            name.replace(&format!("{{{key}}}"), value)
+        });
+
+    let name: String = name
+        .chars()
+        .map(|c| if "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .collect();
+
+    if name.trim().is_empty() {
+        DEFAULT_NAME.to_string()
     } else {
-        float.to_string()
+        name
+    }
+}
+
+/// MIME type and extension of an encoded image
+fn file_type(output: &[u8]) -> (&'static str, &'static str) {
+    match Format::detect(output) {
+        Some(Format::Jpeg) => ("image/jpeg", "jpg"),
+        Some(Format::Webp) => ("image/webp", "webp"),
+        Some(Format::Qoi) => ("image/qoi", "qoi"),
+        Some(Format::Gif) => ("image/gif", "gif"),
+        _ => ("image/png", "png"),
+    }
+}
+
+/// First image of dropped or pasted data
+fn image_file(data: Option<DataTransfer>) -> Option<File> {
+    let files = data?.files()?;
+
+    (0..files.length())
+        .filter_map(|index| files.get(index))
+        .find(|file| file.type_().starts_with("image/"))
+}
+
+fn blob(file: &[u8]) -> Blob {
+    let array = Array::new();
+    array.push(&Uint8Array::from(file).buffer());
+
+    let image = JsValue::from(array);
+    let blob_prop = BlobPropertyBag::new();
+    blob_prop.set_type(file_type(file).0);
+
+    Blob::new_with_u8_array_sequence_and_options(&image, &blob_prop).unwrap()
+}
+
+/// Writes a PNG blob, feature detection errors are returned before writing
+fn write_clipboard(blob: &Blob) -> Result<Promise, JsValue> {
+    // Browsers only accept PNG images
+    if blob.type_() != "image/png" {
+        return Err(js_sys::Error::new("Only PNG images can be copied").into());
+    }
+
+    let window = web_sys::window().unwrap();
+
+    // Both are missing outside secure contexts and in older browsers
+    let clipboard = Reflect::get(&window.navigator(), &"clipboard".into())?;
+    if clipboard.is_undefined() {
+        return Err(js_sys::Error::new("Clipboard needs a secure context").into());
+    }
+    if !Reflect::has(&window, &"ClipboardItem".into())? {
+        return Err(js_sys::Error::new("Clipboard does not support images").into());
+    }
+
+    let items = Object::new();
+    Reflect::set(&items, &blob.type_().into(), &Promise::resolve(blob))?;
+    let item = ClipboardItem::new_with_record_from_str_to_blob_promise(&items)?;
+
+    Ok(clipboard
+        .unchecked_into::<Clipboard>()
+        .write(&Array::of1(&item)))
+}
+
+fn object_url(file: &[u8]) -> String {
+    web_sys::Url::create_object_url_with_blob(&blob(file)).unwrap()
+}
+
+/// Ctrl+Z undoes, Ctrl+Shift+Z and Ctrl+Y redo, text fields keep their own undo
+fn shortcut(event: &web_sys::KeyboardEvent) -> Option<Msg> {
+    let editing = event
+        .target()
+        .and_then(|target| target.dyn_into::<web_sys::HtmlInputElement>().ok())
+        .is_some_and(|input| input.type_() == "text");
+
+    if editing || !(event.ctrl_key() || event.meta_key()) {
+        return None;
+    }
+
+    let msg = match event.key().to_lowercase().as_str() {
+        "z" if event.shift_key() => Msg::Redo,
+        "z" => Msg::Undo,
+        "y" => Msg::Redo,
+        _ => return None,
+    };
+
+    event.prevent_default();
+
+    Some(msg)
+}
+
+/// Sorts a rejected worker call by its error kind
+///
+/// Failed jobs carry a kind, errors without one come from the worker itself.
+fn rejected(error: JsValue) -> Msg {
+    match Reflect::get(&error, &"kind".into()) {
+        Ok(kind) if kind == "cancelled" => Msg::Cancelled,
+        Ok(kind) if kind.is_undefined() => Msg::WorkerFailed(error),
+        _ => Msg::Failed(error),
     }
 }
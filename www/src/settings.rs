@@ -0,0 +1,108 @@
+use std::sync::LazyLock;
+
+use webpixels::{Core, Error, LIMITS, Limit, Options};
+
+/// Numeric option of the settings panel
+pub struct Setting {
+    pub label: &'static str,
+    pub limit: Limit<f64>,
+    pub default: f64,
+    get: fn(&Core) -> f64,
+    set: fn(&mut Core, f64) -> Result<(), Error>,
+}
+
+/// Options of the settings panel, ranges and steps come from [`LIMITS`]
+pub static SETTINGS: LazyLock<[Setting; 8]> = LazyLock::new(|| {
+    let defaults = Options::default();
+
+    [
+        Setting {
+            label: "Min rate",
+            limit: float(LIMITS.min_rate),
+            default: defaults.min_rate.into(),
+            get: |core| core.min_rate().into(),
+            set: |core, value| core.set_min_rate(value.round() as u16),
+        },
+        Setting {
+            label: "Max rate",
+            limit: float(LIMITS.max_rate),
+            default: defaults.max_rate.into(),
+            get: |core| core.max_rate().into(),
+            set: |core, value| core.set_max_rate(value.round() as u16),
+        },
+        Setting {
+            label: "Pixelation",
+            limit: float(LIMITS.pixelation),
+            default: defaults.pixelation.into(),
+            get: |core| core.pixelation().into(),
+            set: |core, value| core.set_pixelation(value.round() as u8),
+        },
+        Setting {
+            label: "Line shift",
+            limit: LIMITS.line_shift,
+            default: defaults.line_shift,
+            get: Core::line_shift,
+            set: Core::set_line_shift,
+        },
+        Setting {
+            label: "Reverse",
+            limit: LIMITS.reverse,
+            default: defaults.reverse,
+            get: Core::reverse,
+            set: Core::set_reverse,
+        },
+        Setting {
+            label: "Flip",
+            limit: LIMITS.flip,
+            default: defaults.flip,
+            get: Core::flip,
+            set: Core::set_flip,
+        },
+        Setting {
+            label: "Channel swap",
+            limit: LIMITS.channel_swap,
+            default: defaults.channel_swap,
+            get: Core::channel_swap,
+            set: Core::set_channel_swap,
+        },
+        Setting {
+            label: "Channel shift",
+            limit: LIMITS.channel_shift,
+            default: defaults.channel_shift,
+            get: Core::channel_shift,
+            set: Core::set_channel_shift,
+        },
+    ]
+});
+
+impl Setting {
+    pub fn get(&self, core: &Core) -> f64 {
+        (self.get)(core)
+    }
+
+    /// Sets a value clamped to the range
+    pub fn set(&self, core: &mut Core, value: f64) -> Result<(), Error> {
+        (self.set)(core, self.limit.clamp(value))
+    }
+
+    pub fn reset(&self, core: &mut Core) -> Result<(), Error> {
+        self.set(core, self.default)
+    }
+
+    /// Formats a value with the precision of the step
+    pub fn format(&self, value: f64) -> String {
+        if self.limit.step >= 1.0 {
+            format!("{value:.0}")
+        } else {
+            format!("{value:.2}")
+        }
+    }
+}
+
+fn float<T: Into<f64>>(limit: Limit<T>) -> Limit<f64> {
+    Limit {
+        min: limit.min.into(),
+        max: limit.max.into(),
+        step: limit.step.into(),
+    }
+}